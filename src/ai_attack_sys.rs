@@ -0,0 +1,56 @@
+use std::time::Duration;
+use specs::prelude::*;
+use crate::animation_sys::{AnimationEvents, AnimationState};
+use crate::behavior_sys::AIBehavior;
+use crate::game_clock::GameClock;
+use crate::turn_sys::Turns;
+use crate::{ActionLock, AIMarker, Attacker, Damage, Death, Glide, GridCoords, PlayerMarker};
+
+/// Makes every AI entity with an `Attacker` hit the player when standing next to them.
+///
+/// The attacker is locked for its cooldown afterwards, which also keeps it from moving.
+/// Entities with a behavior tree attack through its `Attack` action instead.
+pub struct AIAttackSys;
+
+impl<'a> System<'a> for AIAttackSys {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, AIMarker>,
+        ReadStorage<'a, Attacker>,
+        ReadStorage<'a, AIBehavior>,
+        ReadStorage<'a, PlayerMarker>,
+        ReadStorage<'a, GridCoords>,
+        ReadStorage<'a, Death>,
+        WriteStorage<'a, ActionLock>,
+        WriteStorage<'a, Damage>,
+        Read<'a, GameClock>,
+        Read<'a, Turns>,
+        ReadStorage<'a, Glide>,
+        Write<'a, AnimationEvents>,
+    );
+
+    fn run(&mut self, (entities, ai_marker, attackers, behaviors, player_marker, coords, death, mut action_locks, mut damage, clock, turns, glides, mut animations): Self::SystemData) {
+        if !turns.ai_may_act() {
+            return;
+        }
+
+        let Some((player, player_coords)) = (&entities, &player_marker, &coords, !&death).join()
+            .map(|(entity, _, coords, _)| (entity, coords.clone()))
+            .next() else {
+            return;
+        };
+
+        let mut attacked = Vec::new();
+        for (entity, _, attacker, coords, _, _, _, _) in (&entities, &ai_marker, &attackers, &coords, !&behaviors, !&action_locks, !&death, !&glides).join() {
+            if coords.is_next_to(&player_coords) {
+                Damage::inflict(&mut damage, player, attacker.power);
+                animations.push(entity, AnimationState::Attack);
+                attacked.push((entity, attacker.cooldown));
+            }
+        }
+
+        for (entity, cooldown) in attacked {
+            action_locks.insert(entity, ActionLock::new(Duration::from_secs_f64(cooldown), &clock)).expect("ActionLock component added");
+        }
+    }
+}
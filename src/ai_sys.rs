@@ -1,8 +1,15 @@
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use specs::prelude::*;
-use rand::{random, Rng};
-use crate::{ActionLock, AIMarker, GridCoords, GridDimensions, NewGridCoords};
+use rand::Rng;
+use crate::game_clock::GameClock;
+use crate::game_rng::GameRng;
+use crate::behavior_sys::AIBehavior;
+use crate::collisions_sys::PositionsMap;
+use crate::pathfinding::{PathCache, SearchGrid};
+use crate::tile_map::TileMap;
+use crate::turn_sys::Turns;
+use crate::{ActionLock, AIMarker, Death, Destination, Glide, GridCoords, GridDimensions, NewGridCoords, PlayerMarker, PursuitTarget, Pursuer};
 
 
 
@@ -12,49 +19,92 @@ pub struct AISys;
 impl <'a> System<'a> for AISys {
     type SystemData = (Entities<'a>, ReadStorage<'a, AIMarker>, ReadStorage<'a, GridCoords>, WriteStorage<'a, NewGridCoords>,
                     WriteStorage<'a, ActionLock>,
-                       Read<'a, Arc<Mutex<GridDimensions>>>);
+                       Read<'a, Arc<Mutex<GridDimensions>>>, Read<'a, GameClock>, Write<'a, GameRng>,
+                       Read<'a, TileMap>, Read<'a, PositionsMap>,
+                       ReadStorage<'a, Pursuer>, ReadStorage<'a, PlayerMarker>, ReadStorage<'a, Death>, Write<'a, PathCache>,
+                       ReadStorage<'a, AIBehavior>, Read<'a, Turns>, ReadStorage<'a, Glide>,
+                       ReadStorage<'a, Destination>);
 
-    fn run(&mut self, (entities, ai_marker, grid_coord, mut new_grid_coord, mut action_loc, grid_dims): Self::SystemData) {
-        let (columns, rows)  = grid_dims.lock().map(|gd| (gd.grid_columns()-1, gd.grid_rows()-1)).unwrap();
+    fn run(&mut self, (entities, ai_marker, grid_coord, mut new_grid_coord, mut action_loc, grid_dims, clock, mut rng, tile_map, positions_map,
+                       pursuers, player_marker, death, mut path_cache, behaviors, turns, glides, destinations): Self::SystemData) {
+        if !turns.ai_may_act() {
+            return;
+        }
+        let grid_dims = grid_dims.lock().unwrap();
+        let (columns, rows)  = (grid_dims.grid_columns()-1, grid_dims.grid_rows()-1);
+
+        // Tiles taken by someone else are not worth trying; `UpdatePos` has the final say.
+        let free = |x: u64, y: u64, entity: Entity| tile_map.is_walkable(x, y) && positions_map.is_free_for(x, y, entity);
 
+        let player_coords = (&player_marker, &grid_coord, !&death).join()
+            .map(|(_, coords, _)| (coords.x, coords.y))
+            .next();
+        path_cache.retain(|entity| entities.is_alive(entity)
+            && (pursuers.contains(entity) || behaviors.contains(entity) || destinations.contains(entity)));
+
+        // Entities with a behavior tree are moved by `BehaviorSys` instead.
         let mut moved_entities = Vec::new();
-        for (entity, _, grid_coord, _) in (&entities, &ai_marker, &grid_coord, !&action_loc).join() {
-            if rand::random::<bool>() {
-                let mut options_x = Vec::new();
-                options_x.push(grid_coord.x);
-                if grid_coord.x < columns {
-                    options_x.push(grid_coord.x+1);
-                }
-                if grid_coord.x > 0 {
-                    options_x.push(grid_coord.x-1);
-                }
-                let mut r = rand::thread_rng();
-                r.shuffle(&mut options_x);
-                new_grid_coord.insert(entity, NewGridCoords {
-                    x: options_x.get(0).unwrap().clone(),
-                    y: grid_coord.y
-                });
-            } else {
-                let mut options_y = Vec::new();
-                options_y.push(grid_coord.y);
-                if grid_coord.y < rows {
-                    options_y.push(grid_coord.y+1);
-                }
-                if grid_coord.y > 0 {
-                    options_y.push(grid_coord.y-1);
+        for (entity, _, grid_coord, _, pursuer, _, _) in (&entities, &ai_marker, &grid_coord, !&action_loc, pursuers.maybe(), !&behaviors, !&glides).join() {
+            if let Some(pursuer) = pursuer {
+                let goal = match pursuer.target {
+                    PursuitTarget::Player => player_coords,
+                    PursuitTarget::Tile(x, y) => Some((x, y)),
+                };
+                let start = (grid_coord.x, grid_coord.y);
+                let Some(goal) = goal.filter(|goal| *goal != start) else {
+                    continue;
+                };
+
+                let search = SearchGrid::new(&grid_dims, pursuer.neighbourhood, |x, y| free(x, y, entity));
+                // The goal itself may be taken, e.g. by the player being chased; stop next to it.
+                if let Some(next) = path_cache.next_step(entity, start, goal, &search).filter(|next| free(next.0, next.1, entity)) {
+                    new_grid_coord.insert(entity, NewGridCoords { x: next.0, y: next.1 }).expect("NewGridCoords component added");
+                    moved_entities.push(entity);
                 }
-                let mut r = rand::thread_rng();
-                r.shuffle(&mut options_y);
-                new_grid_coord.insert(entity, NewGridCoords {
-                    x: grid_coord.x,
-                    y: options_y.get(0).unwrap().clone(),
-                });
+                continue;
             }
+
+            let step = random_step(&mut *rng, grid_coord, columns, rows, |x, y| free(x, y, entity));
+            new_grid_coord.insert(entity, step).expect("NewGridCoords component added");
             moved_entities.push(entity);
         }
 
         for e in moved_entities {
-            action_loc.insert(e, ActionLock::new(Duration::from_millis(500)));
+            action_loc.insert(e, ActionLock::new(Duration::from_millis(500), &clock)).expect("ActionLock component added");
+        }
+    }
+}
+
+/// One random step of at most one tile along a single axis, staying put when the chosen
+/// neighbours are not `free`.
+pub fn random_step<R: Rng>(rng: &mut R, coords: &GridCoords, columns: u64, rows: u64, free: impl Fn(u64, u64) -> bool) -> NewGridCoords {
+    if rng.gen::<bool>() {
+        let mut options_x = Vec::new();
+        options_x.push(coords.x);
+        if coords.x < columns && free(coords.x+1, coords.y) {
+            options_x.push(coords.x+1);
+        }
+        if coords.x > 0 && free(coords.x-1, coords.y) {
+            options_x.push(coords.x-1);
+        }
+        rng.shuffle(&mut options_x);
+        NewGridCoords {
+            x: *options_x.first().unwrap(),
+            y: coords.y
+        }
+    } else {
+        let mut options_y = Vec::new();
+        options_y.push(coords.y);
+        if coords.y < rows && free(coords.x, coords.y+1) {
+            options_y.push(coords.y+1);
+        }
+        if coords.y > 0 && free(coords.x, coords.y-1) {
+            options_y.push(coords.y-1);
+        }
+        rng.shuffle(&mut options_y);
+        NewGridCoords {
+            x: coords.x,
+            y: *options_y.first().unwrap(),
         }
     }
-}
\ No newline at end of file
+}
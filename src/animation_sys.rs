@@ -0,0 +1,217 @@
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use specs::prelude::*;
+use crate::game_clock::GameClock;
+use crate::{Death, Glide, PlayerSprite};
+
+/// One frame of a clip: the name of a rect in the sprite's `frames` and how long it shows.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ClipFrame {
+    pub frame: String,
+    pub duration: f64,
+}
+
+/// Frames played in order, either over and over or once, holding the last one.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AnimationClip {
+    pub frames: Vec<ClipFrame>,
+    #[serde(default)]
+    pub looping: bool,
+}
+
+/// The clip a sprite is playing and how far it has got.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Playback {
+    clip: String,
+    state: AnimationState,
+    frame: usize,
+    frame_started: Duration,
+    done: bool,
+}
+
+/// What an entity is doing, as far as its animation is concerned. Later states take
+/// precedence over earlier ones.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AnimationState {
+    #[default]
+    Idle,
+    Walk,
+    Attack,
+    Hurt,
+    Die,
+}
+
+impl AnimationState {
+    fn clip_name(&self) -> &'static str {
+        match self {
+            AnimationState::Idle => "idle",
+            AnimationState::Walk => "walk",
+            AnimationState::Attack => "attack",
+            AnimationState::Hurt => "hurt",
+            AnimationState::Die => "die",
+        }
+    }
+}
+
+/// Gameplay events that start a one-shot clip, queued by the systems causing them.
+#[derive(Default)]
+pub struct AnimationEvents(Vec<(Entity, AnimationState)>);
+
+impl AnimationEvents {
+    pub fn push(&mut self, entity: Entity, state: AnimationState) {
+        self.0.push((entity, state));
+    }
+}
+
+/// Picks the clip of every sprite that has any and advances it with the game clock.
+///
+/// Walking and idling follow from whether the entity is gliding; attack, hurt and die
+/// come from `AnimationEvents`, play once and are only cut short by a state of higher
+/// precedence. A clip named `<state>_<facing>` is preferred over plain `<state>`.
+pub struct AnimationSys;
+
+impl<'a> System<'a> for AnimationSys {
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, PlayerSprite>,
+        ReadStorage<'a, Glide>,
+        ReadStorage<'a, Death>,
+        Write<'a, AnimationEvents>,
+        Read<'a, GameClock>,
+    );
+
+    fn run(&mut self, (entities, mut sprites, glides, death, mut events, clock): Self::SystemData) {
+        let events = std::mem::take(&mut events.0);
+
+        for (entity, sprite) in (&entities, &mut sprites).join() {
+            if sprite.clips.is_empty() {
+                continue;
+            }
+
+            let ongoing = if death.contains(entity) {
+                AnimationState::Die
+            } else if glides.contains(entity) {
+                AnimationState::Walk
+            } else {
+                AnimationState::Idle
+            };
+            let triggered = events.iter()
+                .filter(|(e, _)| *e == entity)
+                .map(|(_, state)| *state)
+                .max();
+
+            let playing = &sprite.playback;
+            let one_shot_running = playing.state > AnimationState::Walk && !playing.done;
+            let wanted = match triggered {
+                Some(state) if !one_shot_running || state >= playing.state => Some(state),
+                _ if one_shot_running && playing.state >= ongoing => None,
+                _ => Some(ongoing),
+            };
+
+            if let Some(state) = wanted {
+                if let Some(clip) = sprite.clip_for(state) {
+                    if clip != sprite.playback.clip || triggered == Some(state) {
+                        sprite.playback = Playback {
+                            clip,
+                            state,
+                            frame: 0,
+                            frame_started: clock.now(),
+                            done: false,
+                        };
+                    }
+                }
+            }
+
+            sprite.advance(&clock);
+        }
+    }
+}
+
+impl PlayerSprite {
+    fn clip_for(&self, state: AnimationState) -> Option<String> {
+        let name = state.clip_name();
+        [format!("{}_{}", name, self.facing), name.to_string()].into_iter()
+            .find(|clip| self.clips.contains_key(clip))
+    }
+
+    fn advance(&mut self, clock: &GameClock) {
+        let Some(clip) = self.clips.get(&self.playback.clip) else {
+            return;
+        };
+        if clip.frames.is_empty() {
+            return;
+        }
+
+        let playback = &mut self.playback;
+        while !playback.done {
+            let shown_for = clock.elapsed_since(playback.frame_started).as_secs_f64();
+            let duration = clip.frames[playback.frame].duration;
+            if shown_for < duration || duration <= 0.0 {
+                break;
+            }
+            playback.frame_started += Duration::from_secs_f64(duration);
+            if playback.frame + 1 < clip.frames.len() {
+                playback.frame += 1;
+            } else if clip.looping {
+                playback.frame = 0;
+            } else {
+                playback.done = true;
+            }
+        }
+
+        let frame = clip.frames[playback.frame].frame.clone();
+        self.update_frame(&frame);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::*;
+
+    fn clip(frames: &[&str], looping: bool) -> AnimationClip {
+        AnimationClip {
+            frames: frames.iter().map(|f| ClipFrame { frame: f.to_string(), duration: 0.25 }).collect(),
+            looping,
+        }
+    }
+
+    fn world() -> (World, Entity) {
+        let mut world = World::new();
+        System::setup(&mut AnimationSys, &mut world);
+        world.insert(GameClock::default());
+        let frames = ["idle1", "idle2", "die1", "die2"].iter()
+            .map(|f| (f.to_string(), [0.0, 0.0, 32.0, 32.0]))
+            .collect();
+        let clips = HashMap::from([
+            ("idle".to_string(), clip(&["idle1", "idle2"], true)),
+            ("die".to_string(), clip(&["die1", "die2"], false)),
+        ]);
+        let sprite = PlayerSprite { frames, clips, ..Default::default() };
+        let entity = world.create_entity().with(sprite).build();
+        (world, entity)
+    }
+
+    /// Advances the clock by `dt` seconds, animates and returns the frame shown.
+    fn frame_after(world: &mut World, entity: Entity, dt: f64) -> String {
+        world.write_resource::<GameClock>().advance(dt);
+        AnimationSys.run_now(world);
+        world.read_storage::<PlayerSprite>().get(entity).unwrap().current_frame.clone()
+    }
+
+    #[test]
+    fn looping_clip_wraps_around() {
+        let (mut world, entity) = world();
+        let frames: Vec<_> = [0.0, 0.25, 0.25, 0.25, 0.25].iter().map(|dt| frame_after(&mut world, entity, *dt)).collect();
+        assert_eq!(frames, ["idle1", "idle2", "idle1", "idle2", "idle1"]);
+    }
+
+    #[test]
+    fn one_shot_clip_holds_its_last_frame() {
+        let (mut world, entity) = world();
+        frame_after(&mut world, entity, 0.0);
+        world.write_storage::<Death>().insert(entity, Death).unwrap();
+        let frames: Vec<_> = [0.0, 0.25, 0.25, 0.25, 1.0].iter().map(|dt| frame_after(&mut world, entity, *dt)).collect();
+        assert_eq!(frames, ["die1", "die2", "die2", "die2", "die2"]);
+    }
+}
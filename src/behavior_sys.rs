@@ -0,0 +1,202 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use ai_behavior::{Behavior, State, Status, Failure, Running, Success, RUNNING};
+use piston_window::{Event, Loop};
+use serde::{Deserialize, Serialize};
+use specs::{Component, VecStorage};
+use specs::prelude::*;
+use crate::ai_sys::random_step;
+use crate::animation_sys::{AnimationEvents, AnimationState};
+use crate::collisions_sys::PositionsMap;
+use crate::game_clock::GameClock;
+use crate::game_rng::GameRng;
+use crate::pathfinding::{Neighbourhood, PathCache, SearchGrid};
+use crate::simulation::update_event;
+use crate::tile_map::TileMap;
+use crate::turn_sys::Turns;
+use crate::{ActionLock, AIMarker, Attacker, Damage, Death, Glide, GridCoords, GridDimensions, Health, InputEvent, NewGridCoords, PlayerMarker};
+
+/// How long an entity waits after each step.
+const STEP_LOCK: Duration = Duration::from_millis(500);
+
+/// Leaves of an AI behavior tree.
+///
+/// Conditions answer at once. Actions that move or attack keep running while the entity
+/// is locked from its previous one and succeed once they have taken a single step or hit,
+/// so a tree decides again after every tile.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum AIAction {
+    /// Succeeds when the player is at most this many tiles away.
+    PlayerWithin(u64),
+    /// Succeeds when the player is on one of the eight surrounding tiles.
+    PlayerAdjacent,
+    /// Succeeds when the entity's health is below this value.
+    HealthBelow(u8),
+    /// Steps to a random neighbouring tile, or stays put.
+    Wander,
+    /// Steps along the shortest path towards the player; fails when there is none.
+    Chase,
+    /// Steps to the neighbouring tile furthest from the player; fails when cornered.
+    Flee,
+    /// Hits the adjacent player using the entity's `Attacker`, keeping running while it is
+    /// locked; fails when the player is not adjacent or the entity cannot attack.
+    Attack,
+}
+
+/// A behavior tree driving an AI entity, together with how far it has got.
+///
+/// The tree starts over whenever it succeeds or fails.
+#[derive(Component, Clone, Serialize, Deserialize)]
+#[storage(VecStorage)]
+pub struct AIBehavior {
+    behavior: Behavior<AIAction>,
+    state: State<AIAction, ()>,
+}
+
+impl AIBehavior {
+    pub fn new(behavior: Behavior<AIAction>) -> Self {
+        Self {
+            state: State::new(behavior.clone()),
+            behavior,
+        }
+    }
+}
+
+/// Ticks every behavior tree on each update event, with the game clock's time scale applied,
+/// or once per AI turn in turn-based mode.
+pub struct BehaviorSys;
+
+impl<'a> System<'a> for BehaviorSys {
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, InputEvent>,
+        WriteStorage<'a, AIBehavior>,
+        ReadStorage<'a, AIMarker>,
+        ReadStorage<'a, PlayerMarker>,
+        ReadStorage<'a, GridCoords>,
+        ReadStorage<'a, Health>,
+        ReadStorage<'a, Death>,
+        WriteStorage<'a, NewGridCoords>,
+        WriteStorage<'a, ActionLock>,
+        WriteStorage<'a, Damage>,
+        Read<'a, Arc<Mutex<GridDimensions>>>,
+        Read<'a, GameClock>,
+        Write<'a, GameRng>,
+        Read<'a, TileMap>,
+        Read<'a, PositionsMap>,
+        Write<'a, PathCache>,
+        ReadStorage<'a, Attacker>,
+        Read<'a, Turns>,
+        ReadStorage<'a, Glide>,
+        Write<'a, AnimationEvents>,
+    );
+
+    fn run(&mut self, (entities, input, mut behaviors, ai_marker, player_marker, grid_coords, health, death, mut new_grid_coords,
+                       mut action_locks, mut damage, grid_dims, clock, mut rng, tile_map, positions_map, mut path_cache, attackers, turns, glides, mut animations): Self::SystemData) {
+        // A turn lasts as long as a step does in real time, so waits in the trees keep
+        // their proportions in both modes.
+        let dt = match input.0 {
+            _ if turns.is_turn_based() && turns.ai_may_act() => STEP_LOCK.as_secs_f64(),
+            Some(Event::Loop(Loop::Update(args))) if !turns.is_turn_based() => clock.scaled(args.dt),
+            _ => return,
+        };
+        let event = update_event(dt);
+
+        let grid_dims = grid_dims.lock().unwrap();
+        let (columns, rows) = (grid_dims.grid_columns()-1, grid_dims.grid_rows()-1);
+        let free = |x: u64, y: u64, entity: Entity| tile_map.is_walkable(x, y) && positions_map.is_free_for(x, y, entity);
+
+        let player = (&entities, &player_marker, &grid_coords, !&death).join()
+            .map(|(entity, _, coords, _)| (entity, coords.clone()))
+            .next();
+
+        for (entity, behavior, _, coords, _) in (&entities, &mut behaviors, &ai_marker, &grid_coords, !&death).join() {
+            let start = (coords.x, coords.y);
+            let locked = action_locks.contains(entity) || glides.contains(entity);
+            let mut step_to = None;
+            let mut lock_for = None;
+
+            let (status, _) = behavior.state.event(&event, &mut |args| {
+                let player_distance = player.as_ref()
+                    .map(|(_, p)| Neighbourhood::Eight.distance(start, (p.x, p.y)));
+
+                let acting = matches!(args.action, AIAction::Wander | AIAction::Chase | AIAction::Flee | AIAction::Attack);
+                if acting && (locked || lock_for.is_some()) {
+                    return RUNNING;
+                }
+
+                let status = match *args.action {
+                    AIAction::PlayerWithin(range) => succeed_if(player_distance.is_some_and(|d| d <= range)),
+                    AIAction::PlayerAdjacent => succeed_if(player_distance == Some(1)),
+                    AIAction::HealthBelow(value) => succeed_if(health.get(entity).is_some_and(|h| h.0 < value)),
+                    AIAction::Wander => {
+                        let step = random_step(&mut *rng, coords, columns, rows, |x, y| free(x, y, entity));
+                        step_to = Some((step.x, step.y));
+                        lock_for = Some(STEP_LOCK);
+                        Success
+                    }
+                    AIAction::Chase => match &player {
+                        Some((_, p)) if player_distance == Some(1) || (p.x, p.y) == start => Success,
+                        Some((_, p)) => {
+                            let search = SearchGrid::new(&grid_dims, Neighbourhood::Four, |x, y| free(x, y, entity));
+                            match path_cache.next_step(entity, start, (p.x, p.y), &search) {
+                                Some(next) if free(next.0, next.1, entity) => {
+                                    step_to = Some(next);
+                                    lock_for = Some(STEP_LOCK);
+                                    Success
+                                }
+                                // Blocked for now, e.g. by another entity; try again next tick.
+                                Some(_) => Running,
+                                None => Failure,
+                            }
+                        }
+                        None => Failure,
+                    },
+                    AIAction::Flee => match &player {
+                        Some((_, p)) => {
+                            let away = |tile: (u64, u64)| Neighbourhood::Four.distance(tile, (p.x, p.y));
+                            let mut options = Vec::new();
+                            if start.1 > 0 { options.push((start.0, start.1 - 1)); }
+                            if start.0 < columns { options.push((start.0 + 1, start.1)); }
+                            if start.1 < rows { options.push((start.0, start.1 + 1)); }
+                            if start.0 > 0 { options.push((start.0 - 1, start.1)); }
+
+                            let best = options.into_iter()
+                                .filter(|&(x, y)| free(x, y, entity) && away((x, y)) > away(start))
+                                .max_by_key(|&tile| away(tile));
+                            step_to = best;
+                            lock_for = best.map(|_| STEP_LOCK);
+                            succeed_if(best.is_some())
+                        }
+                        None => Failure,
+                    },
+                    AIAction::Attack => match (&player, attackers.get(entity)) {
+                        (Some((target, _)), Some(attacker)) if player_distance == Some(1) => {
+                            Damage::inflict(&mut damage, *target, attacker.power);
+                            animations.push(entity, AnimationState::Attack);
+                            lock_for = Some(Duration::from_secs_f64(attacker.cooldown));
+                            Success
+                        }
+                        _ => Failure,
+                    },
+                };
+                (status, args.dt)
+            });
+
+            if status != Running {
+                behavior.state = State::new(behavior.behavior.clone());
+            }
+
+            if let Some((x, y)) = step_to {
+                new_grid_coords.insert(entity, NewGridCoords { x, y }).expect("NewGridCoords component added");
+            }
+            if let Some(duration) = lock_for {
+                action_locks.insert(entity, ActionLock::new(duration, &clock)).expect("ActionLock component added");
+            }
+        }
+    }
+}
+
+fn succeed_if(condition: bool) -> Status {
+    if condition { Success } else { Failure }
+}
@@ -1,6 +1,7 @@
 use nalgebra::Vector4;
-use specs::{Entities, Join, ReadStorage, System, WriteStorage};
+use specs::{Entities, Join, ReadStorage, System, Write, WriteStorage};
 use crate::{BlobMarker, Color, Damage, GridCoords, PlayerMarker};
+use crate::animation_sys::{AnimationEvents, AnimationState};
 use crate::input_sys::ActionFired;
 
 pub struct BlobInteractionSys;
@@ -13,17 +14,18 @@ impl<'a> System<'a> for BlobInteractionSys {
         ReadStorage<'a, GridCoords>,
         WriteStorage<'a, ActionFired>,
         WriteStorage<'a, Damage>,
-        WriteStorage<'a, Color>
+        WriteStorage<'a, Color>,
+        Write<'a, AnimationEvents>,
     );
 
-    fn run(&mut self, (entities, player, blob, coords, mut actions,  mut damage, mut colors): Self::SystemData) {
+    fn run(&mut self, (entities, player, blob, coords, mut actions,  mut damage, mut colors, mut animations): Self::SystemData) {
 
         let player_actions = (&entities, &player, &actions).join().filter(|data|!data.2.is_handled()).map(|(e,_,_)| e).collect::<Vec<_>>();
 
         for (_, c1) in (&player, &coords).join() {
             for (blob_entity, _, c2, color) in (&entities, &blob, &coords, &mut colors).join() {
                 if c1.is_next_to(c2) {
-                    if player_actions.len() > 0 {
+                    if !player_actions.is_empty() {
                         color.0 = Vector4::new(1.0, 0.0, 0.0, 1.0);
                         damage.insert(blob_entity, Damage(5)).expect("Damage component added");
                     } else {
@@ -36,8 +38,9 @@ impl<'a> System<'a> for BlobInteractionSys {
         }
 
         for e in player_actions {
-            let mut action = actions.get_mut(e).unwrap();
+            let action = actions.get_mut(e).unwrap();
             action.mark_as_handled();
+            animations.push(e, AnimationState::Attack);
             // actions.remove(e);
         }
 
@@ -0,0 +1,282 @@
+use std::sync::{Arc, Mutex};
+use piston_window::math::Matrix2d;
+use piston_window::Transformed;
+use specs::prelude::*;
+use crate::input_map::{Action, PlayerActions};
+use crate::{GridDimensions, PlayerMarker, Position};
+
+const MIN_ZOOM: f64 = 0.5;
+const MAX_ZOOM: f64 = 3.0;
+const ZOOM_STEP: f64 = 1.25;
+
+/// Which part of the map the board shows and how large.
+///
+/// The grid is the same whatever the window size: resizing the window scales the view
+/// by how much the board grew or shrank against the size the level was laid out for,
+/// so entities keep their grid and world positions and never fall off the map.
+///
+/// Positions come in three spaces: grid coordinates, world pixels as stored in
+/// `Position`, and screen pixels in the window. `GridDimensions` converts between the
+/// first two, the camera between the last two.
+#[derive(Debug, Clone)]
+pub struct Camera {
+    /// World position shown in the middle of the board.
+    center: (f64, f64),
+    zoom: f64,
+    /// Size of the board on screen, i.e. the window without the HUD.
+    viewport: (f64, f64),
+    /// Size of the board the level was laid out for, shown at `zoom` without scaling.
+    reference: (f64, f64),
+    following: bool,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self::new((0.0, 0.0))
+    }
+}
+
+impl Camera {
+    pub fn new(viewport: (f64, f64)) -> Self {
+        Self {
+            center: (viewport.0 / 2.0, viewport.1 / 2.0),
+            zoom: 1.0,
+            viewport,
+            reference: viewport,
+            following: true,
+        }
+    }
+
+    pub fn set_viewport(&mut self, viewport: (f64, f64)) {
+        self.viewport = viewport;
+    }
+
+    /// Pixels on screen per world pixel: the player's zoom times the window's scaling.
+    fn scale(&self) -> f64 {
+        let (width, height) = self.reference;
+        if width <= 0.0 || height <= 0.0 {
+            return self.zoom;
+        }
+        self.zoom * (self.viewport.0 / width).min(self.viewport.1 / height)
+    }
+
+    /// World position under the screen position `point`, if it is on the board.
+    pub fn screen_to_world(&self, [x, y]: [f64; 2]) -> Option<(f64, f64)> {
+        if x < 0.0 || y < 0.0 || x >= self.viewport.0 || y >= self.viewport.1 {
+            return None;
+        }
+        let scale = self.scale();
+        Some(((x - self.viewport.0 / 2.0) / scale + self.center.0,
+              (y - self.viewport.1 / 2.0) / scale + self.center.1))
+    }
+
+    pub fn world_to_screen(&self, (x, y): (f64, f64)) -> [f64; 2] {
+        let scale = self.scale();
+        [(x - self.center.0) * scale + self.viewport.0 / 2.0,
+         (y - self.center.1) * scale + self.viewport.1 / 2.0]
+    }
+
+    /// `transform` with world positions mapped to where the camera shows them.
+    pub fn transform(&self, transform: Matrix2d) -> Matrix2d {
+        transform
+            .trans(self.viewport.0 / 2.0, self.viewport.1 / 2.0)
+            .zoom(self.scale())
+            .trans(-self.center.0, -self.center.1)
+    }
+
+    /// Keeps the view on the map: centred when the map fits, otherwise without showing
+    /// anything past its edges.
+    fn clamp(&mut self, map_size: (f64, f64)) {
+        let scale = self.scale();
+        let clamp_axis = |center: f64, view: f64, map: f64| {
+            let half = view / scale / 2.0;
+            if 2.0 * half >= map {
+                map / 2.0
+            } else {
+                center.clamp(half, map - half)
+            }
+        };
+        self.center = (clamp_axis(self.center.0, self.viewport.0, map_size.0),
+                       clamp_axis(self.center.1, self.viewport.1, map_size.1));
+    }
+}
+
+/// Pans and zooms the `Camera` on the player's actions and keeps it on the player while
+/// following. Panning stops following until `FollowPlayer`.
+pub struct CameraSys;
+
+impl<'a> System<'a> for CameraSys {
+    type SystemData = (
+        Read<'a, PlayerActions>,
+        Write<'a, Camera>,
+        Read<'a, Arc<Mutex<GridDimensions>>>,
+        ReadStorage<'a, PlayerMarker>,
+        ReadStorage<'a, Position>,
+    );
+
+    fn run(&mut self, (actions, mut camera, grid_dims, player, positions): Self::SystemData) {
+        let grid_dims = grid_dims.lock().unwrap();
+        let (tile_width, tile_height) = grid_dims.tile_size();
+
+        for action in actions.iter() {
+            let pan = match action {
+                Action::PanUp => (0.0, -tile_height),
+                Action::PanDown => (0.0, tile_height),
+                Action::PanLeft => (-tile_width, 0.0),
+                Action::PanRight => (tile_width, 0.0),
+                Action::ZoomIn => {
+                    camera.zoom = (camera.zoom * ZOOM_STEP).min(MAX_ZOOM);
+                    continue;
+                }
+                Action::ZoomOut => {
+                    camera.zoom = (camera.zoom / ZOOM_STEP).max(MIN_ZOOM);
+                    continue;
+                }
+                Action::FollowPlayer => {
+                    camera.following = true;
+                    continue;
+                }
+                _ => continue,
+            };
+            camera.following = false;
+            camera.center.0 += pan.0;
+            camera.center.1 += pan.1;
+        }
+
+        if camera.following {
+            if let Some((_, pos)) = (&player, &positions).join().next() {
+                camera.center = (pos.x + tile_width / 2.0, pos.y + tile_height / 2.0);
+            }
+        }
+        camera.clamp(grid_dims.map_size());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid_dimensions::tests::grid;
+
+    fn zoomed_camera() -> Camera {
+        let mut camera = Camera::new(grid().board_size());
+        camera.zoom = 2.0;
+        camera.center = (200.0, 100.0);
+        camera
+    }
+
+    #[test]
+    fn screen_and_world_round_trip_when_zoomed() {
+        let camera = zoomed_camera();
+        for point in [[0.0, 0.0], [320.0, 217.5], [17.0, 400.0], [639.0, 434.0]] {
+            let world = camera.screen_to_world(point).unwrap();
+            let [x, y] = camera.world_to_screen(world);
+            assert!((x - point[0]).abs() < 1e-9 && (y - point[1]).abs() < 1e-9);
+        }
+        assert_eq!(camera.world_to_screen((200.0, 100.0)), [320.0, 217.5]);
+        assert_eq!(camera.screen_to_world([320.0 + 40.0, 217.5 + 30.0]), Some((220.0, 115.0)));
+    }
+
+    #[test]
+    fn clicks_land_on_the_tile_drawn_there_when_zoomed() {
+        let camera = zoomed_camera();
+        let grid = grid();
+        for tile in [(1, 0), (4, 2), (7, 5), (2, 5)] {
+            let (x, y) = grid.tile_to_world(tile);
+            let [left, top] = camera.world_to_screen((x, y));
+            // Anywhere inside the tile as drawn, which is twice its size on screen.
+            for (dx, dy) in [(0.5, 0.5), (79.0, 59.0), (40.0, 30.0)] {
+                let world = camera.screen_to_world([left + dx, top + dy]);
+                assert_eq!(world.and_then(|world| grid.world_to_tile(world)), Some(tile));
+            }
+        }
+    }
+
+    #[test]
+    fn transform_matches_world_to_screen() {
+        let camera = zoomed_camera();
+        let transform = camera.transform([[1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]);
+        let (x, y) = (123.0, 45.0);
+        let drawn = [transform[0][0] * x + transform[0][1] * y + transform[0][2],
+                     transform[1][0] * x + transform[1][1] * y + transform[1][2]];
+        assert_eq!(drawn, camera.world_to_screen((x, y)));
+    }
+
+    #[test]
+    fn points_off_the_board_are_not_in_the_world() {
+        let camera = zoomed_camera();
+        assert_eq!(camera.screen_to_world([-1.0, 10.0]), None);
+        assert_eq!(camera.screen_to_world([10.0, 435.0]), None);
+        assert_eq!(camera.screen_to_world([640.0, 10.0]), None);
+    }
+
+    /// Camera of a window fitted to `grid`'s map, clamped like `CameraSys` does.
+    fn fitted_camera(grid: &GridDimensions) -> Camera {
+        let mut camera = Camera::new(grid.map_size());
+        camera.clamp(grid.map_size());
+        camera
+    }
+
+    /// World rectangle the board shows, as `(left, top, right, bottom)`.
+    fn visible(camera: &Camera) -> (f64, f64, f64, f64) {
+        let (left, top) = camera.screen_to_world([0.0, 0.0]).unwrap();
+        let scale = camera.scale();
+        (left, top, left + camera.viewport.0 / scale, top + camera.viewport.1 / scale)
+    }
+
+    #[test]
+    fn clicks_map_to_the_same_tile_after_a_resize() {
+        let grid = grid();
+        let mut camera = fitted_camera(&grid);
+        let tiles = [(0, 0), (5, 0), (3, 3), (11, 7)];
+        let clicked = |camera: &Camera, scale: (f64, f64), tile| {
+            let (x, y) = grid.tile_to_world(tile);
+            let [left, top] = camera.world_to_screen((x, y));
+            camera.screen_to_world([left + 20.0 * scale.0, top + 15.0 * scale.1])
+                .and_then(|world| grid.world_to_tile(world))
+        };
+
+        camera.set_viewport((960.0, 480.0));
+        camera.clamp(grid.map_size());
+        assert_eq!(camera.scale(), 2.0);
+        for tile in tiles {
+            assert_eq!(clicked(&camera, (2.0, 2.0), tile), Some(tile));
+            // The whole board grew, so the tile is where it was, only twice as far out.
+            let (x, y) = grid.tile_to_world(tile);
+            let [left, top] = camera.world_to_screen((x, y));
+            assert_eq!([left, top], [x * 2.0, y * 2.0]);
+        }
+
+        // Only wider: the map keeps its size and is centred.
+        camera.set_viewport((720.0, 240.0));
+        camera.clamp(grid.map_size());
+        assert_eq!(camera.scale(), 1.0);
+        for tile in tiles {
+            assert_eq!(clicked(&camera, (1.0, 1.0), tile), Some(tile));
+        }
+        assert_eq!(camera.world_to_screen((0.0, 0.0)), [120.0, 0.0]);
+    }
+
+    #[test]
+    fn clamp_keeps_the_view_on_the_map_after_a_resize() {
+        let grid = grid();
+        let (map_width, map_height) = grid.map_size();
+        let mut camera = fitted_camera(&grid);
+
+        camera.set_viewport((480.0, 240.0));
+        camera.zoom = 2.0;
+        for center in [(-500.0, -500.0), (1000.0, 30.0), (240.0, 1000.0)] {
+            camera.center = center;
+            camera.clamp(grid.map_size());
+            let (left, top, right, bottom) = visible(&camera);
+            assert!(left >= 0.0 && top >= 0.0 && right <= map_width && bottom <= map_height);
+        }
+
+        // The view shrinks with the board, by the smaller of the two ratios, so less of
+        // the map fits vertically; still nothing past its edges is shown.
+        camera.set_viewport((240.0, 60.0));
+        camera.center = (0.0, 0.0);
+        camera.clamp(grid.map_size());
+        assert_eq!(camera.scale(), 0.5);
+        assert_eq!(visible(&camera), (0.0, 0.0, 480.0, 120.0));
+    }
+}
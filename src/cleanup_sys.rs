@@ -1,7 +1,6 @@
-use std::sync::{Arc, Mutex};
-use nalgebra::Vector4;
-use specs::{Entities, Join, LazyUpdate, Read, ReadStorage, System, Write, WriteStorage};
-use crate::{ActionLock, BlobMarker, Color, GridCoords, GridDimensions, InputEvent, PlayerMarker, Position};
+use specs::{Entities, Join, Read, ReadStorage, System, Write, WriteStorage};
+use crate::{ActionLock, InputEvent, PlayerMarker};
+use crate::game_clock::GameClock;
 use crate::input_sys::ActionFired;
 
 pub struct CleanupSys;
@@ -12,13 +11,14 @@ impl<'a> System<'a> for CleanupSys {
         Write<'a, InputEvent>,
         WriteStorage<'a, ActionFired>,
         WriteStorage<'a, ActionLock>,
-        ReadStorage<'a, PlayerMarker>,);
+        ReadStorage<'a, PlayerMarker>,
+        Read<'a, GameClock>);
 
-    fn run(&mut self, (entities, mut input_ev, mut actions, mut locks, player): Self::SystemData) {
+    fn run(&mut self, (entities, mut input_ev, mut actions, mut locks, player, clock): Self::SystemData) {
         input_ev.0 = None;
 
         let clean_actions = (&entities, &actions, &player).join()
-            .filter(|data|data.1.is_expired())
+            .filter(|data|data.1.is_expired(&clock))
                 .map(|(e,_,_)|e)
             .collect::<Vec<_>>();
 
@@ -26,7 +26,7 @@ impl<'a> System<'a> for CleanupSys {
             actions.remove(entity);
         }
 
-        let locks_to_clean = (&entities, &locks).join().filter_map(|a|if a.1.is_expired() {
+        let locks_to_clean = (&entities, &locks).join().filter_map(|a|if a.1.is_expired(&clock) {
             Some(a.0)
         } else {
             None
@@ -1,63 +1,161 @@
-// use specs::{Entities, Join, ReadStorage, System, Write, WriteStorage};
-// use piston_window::{Button, ButtonState, Key};
-// use crate::{GRID_HEIGHT, GRID_WIDTH, GridCoords, InputEvent, PlayerMarker, Position, Velocity};
-//
-// pub struct CollisionsSys;
-// impl<'a> System<'a> for CollisionsSys {
-//     type SystemData = (Entities<'a>, ReadStorage<'a, Position>, ReadStorage<'a, Velocity>,
-//                        ReadStorage<'a, GridCoords>);
-//
-//     fn run(&mut self, (entities, positions, velocity, coords): Self::SystemData) {
-//         // println!("Print events:");
-//         let x_step = 50.0;
-//         let y_step = 50.0;
-//
-//
-//         if let Some((btn, btn_state)) =  inp.0.pop() {
-//             for (_, v, grid_coord, _) in (&pos, &mut vs, &mut grid_coords, &player).join() {
-//                 if btn_state == ButtonState::Press {
-//                     match btn {
-//                         Button::Keyboard(k) => {
-//                             match k {
-//                                 Key::Right => {
-//                                     if grid_coord.x + 1 <= GRID_WIDTH {
-//                                         v.x = x_step;
-//                                         grid_coord.x += 1;
-//                                     }
-//                                 }
-//                                 Key::Left => {
-//                                     if grid_coord.x > 0 {
-//                                         v.x = -x_step;
-//                                         grid_coord.x -= 1;
-//                                     }
-//                                 }
-//                                 Key::Down => {
-//                                     if grid_coord.y + 1 <= GRID_HEIGHT {
-//                                         v.y = y_step;
-//                                         grid_coord.y += 1;
-//                                     }
-//                                 }
-//                                 Key::Up => {
-//                                     if grid_coord.y > 0 {
-//                                         v.y = -y_step;
-//                                         grid_coord.y -= 1;
-//                                     }
-//                                 }
-//                                 _ => {}
-//                             }
-//                         }
-//                         _ =>{}
-//                     }
-//                 } else {
-//                     v.x = 0.0;
-//                     v.y = 0.0;
-//                 }
-//             }
-//         } else {
-//             for (_, v) in (&pos, &mut vs).join() {
-//                 v.x = 0.0;
-//                 v.y = 0.0;
-//             }
-//         }
-//     }
-// }
+use std::collections::{HashMap, HashSet};
+use specs::Entity;
+
+/// Which entity stands on each occupied tile.
+///
+/// `UpdatePos` rebuilds it from the `GridCoords` before resolving the tick's moves and
+/// keeps it current as moves are accepted, so systems running after it see the tiles as
+/// they are now. `PointerSys`, `AISys` and `BehaviorSys` run before it and see the tiles
+/// as the previous tick left them.
+#[derive(Default, Debug)]
+pub struct PositionsMap(HashMap<(u64, u64), Entity>);
+
+impl PositionsMap {
+    pub fn rebuild(&mut self, occupants: impl Iterator<Item = (Entity, (u64, u64))>) {
+        self.0.clear();
+        for (entity, tile) in occupants {
+            self.0.insert(tile, entity);
+        }
+    }
+
+    pub fn occupant(&self, x: u64, y: u64) -> Option<Entity> {
+        self.0.get(&(x, y)).copied()
+    }
+
+    /// Whether `entity` may stand on the tile, i.e. it is empty or `entity` is already there.
+    pub fn is_free_for(&self, x: u64, y: u64, entity: Entity) -> bool {
+        self.occupant(x, y).is_none_or(|e| e == entity)
+    }
+
+    pub fn move_entity(&mut self, entity: Entity, from: (u64, u64), to: (u64, u64)) {
+        if self.0.get(&from) == Some(&entity) {
+            self.0.remove(&from);
+        }
+        self.0.insert(to, entity);
+    }
+
+    /// Decides which of this tick's move requests are accepted, updating the map for them.
+    ///
+    /// Requests are handled in order of `priority` (lower first), then entity id, so the
+    /// outcome never depends on storage iteration order. A move is accepted when its target
+    /// is free at the time it is handled; moving into a tile that another entity leaves in
+    /// the same tick only succeeds if that entity was handled first.
+    pub fn resolve_moves(&mut self, mut requests: Vec<MoveRequest>) -> Vec<MoveRequest> {
+        requests.sort_by_key(|r| (r.priority, r.entity.id()));
+
+        let mut claimed = HashSet::new();
+        let mut accepted = Vec::new();
+        for request in requests {
+            if request.from == request.to {
+                continue;
+            }
+            let (x, y) = request.to;
+            if claimed.contains(&request.to) || !self.is_free_for(x, y, request.entity) {
+                continue;
+            }
+            claimed.insert(request.to);
+            self.move_entity(request.entity, request.from, request.to);
+            accepted.push(request);
+        }
+        accepted
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MoveRequest {
+    pub entity: Entity,
+    pub priority: u8,
+    pub from: (u64, u64),
+    pub to: (u64, u64),
+}
+
+#[cfg(test)]
+mod tests {
+    use specs::{World, WorldExt};
+    use super::*;
+
+    /// `count` entities, in increasing id order.
+    fn entities(count: usize) -> Vec<Entity> {
+        let world = World::new();
+        let entities = (0..count).map(|_| world.entities().create()).collect::<Vec<_>>();
+        assert!(entities.windows(2).all(|pair| pair[0].id() < pair[1].id()));
+        entities
+    }
+
+    fn request(entity: Entity, priority: u8, from: (u64, u64), to: (u64, u64)) -> MoveRequest {
+        MoveRequest { entity, priority, from, to }
+    }
+
+    fn map(occupants: &[(Entity, (u64, u64))]) -> PositionsMap {
+        let mut map = PositionsMap::default();
+        map.rebuild(occupants.iter().copied());
+        map
+    }
+
+    fn movers(accepted: &[MoveRequest]) -> Vec<Entity> {
+        accepted.iter().map(|r| r.entity).collect()
+    }
+
+    #[test]
+    fn lower_id_wins_a_contested_tile() {
+        let e = entities(2);
+        let mut positions = map(&[(e[0], (0, 1)), (e[1], (2, 1))]);
+        // Requests come in any order; the outcome does not depend on it.
+        let accepted = positions.resolve_moves(vec![request(e[1], 1, (2, 1), (1, 1)), request(e[0], 1, (0, 1), (1, 1))]);
+        assert_eq!(movers(&accepted), vec![e[0]]);
+        assert_eq!(positions.occupant(1, 1), Some(e[0]));
+        assert_eq!(positions.occupant(2, 1), Some(e[1]));
+        assert_eq!(positions.occupant(0, 1), None);
+    }
+
+    #[test]
+    fn priority_beats_id() {
+        let e = entities(2);
+        let mut positions = map(&[(e[0], (0, 1)), (e[1], (2, 1))]);
+        let accepted = positions.resolve_moves(vec![request(e[0], 1, (0, 1), (1, 1)), request(e[1], 0, (2, 1), (1, 1))]);
+        assert_eq!(movers(&accepted), vec![e[1]]);
+        assert_eq!(positions.occupant(1, 1), Some(e[1]));
+    }
+
+    #[test]
+    fn entities_cannot_swap_tiles() {
+        let e = entities(2);
+        let mut positions = map(&[(e[0], (0, 0)), (e[1], (1, 0))]);
+        let accepted = positions.resolve_moves(vec![request(e[0], 1, (0, 0), (1, 0)), request(e[1], 1, (1, 0), (0, 0))]);
+        assert!(accepted.is_empty());
+        assert_eq!(positions.occupant(0, 0), Some(e[0]));
+        assert_eq!(positions.occupant(1, 0), Some(e[1]));
+    }
+
+    #[test]
+    fn vacated_tile_can_be_entered_by_someone_handled_later() {
+        let e = entities(2);
+        let mut positions = map(&[(e[0], (1, 0)), (e[1], (0, 0))]);
+        let accepted = positions.resolve_moves(vec![request(e[1], 1, (0, 0), (1, 0)), request(e[0], 1, (1, 0), (2, 0))]);
+        assert_eq!(movers(&accepted), vec![e[0], e[1]]);
+        assert_eq!(positions.occupant(2, 0), Some(e[0]));
+        assert_eq!(positions.occupant(1, 0), Some(e[1]));
+        assert_eq!(positions.occupant(0, 0), None);
+    }
+
+    #[test]
+    fn vacated_tile_cannot_be_entered_by_someone_handled_earlier() {
+        let e = entities(2);
+        let mut positions = map(&[(e[0], (0, 0)), (e[1], (1, 0))]);
+        let accepted = positions.resolve_moves(vec![request(e[0], 1, (0, 0), (1, 0)), request(e[1], 1, (1, 0), (2, 0))]);
+        assert_eq!(movers(&accepted), vec![e[1]]);
+        assert_eq!(positions.occupant(0, 0), Some(e[0]));
+        assert_eq!(positions.occupant(2, 0), Some(e[1]));
+    }
+
+    #[test]
+    fn stationary_entity_keeps_its_tile() {
+        let e = entities(2);
+        let mut positions = map(&[(e[0], (1, 0)), (e[1], (0, 0))]);
+        let accepted = positions.resolve_moves(vec![request(e[1], 0, (0, 0), (1, 0))]);
+        assert!(accepted.is_empty());
+        assert_eq!(positions.occupant(1, 0), Some(e[0]));
+        assert!(positions.is_free_for(1, 0, e[0]));
+        assert!(!positions.is_free_for(1, 0, e[1]));
+    }
+}
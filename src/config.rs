@@ -0,0 +1,128 @@
+use std::env;
+use std::path::PathBuf;
+use std::time::Duration;
+use crate::movement_sys::{Easing, MovementSettings};
+use crate::turn_sys::TurnMode;
+
+/// Options read from the command line.
+#[derive(Debug)]
+pub struct GameConfig {
+    /// Run without opening a window, then exit.
+    pub headless: bool,
+    /// Number of 60Hz updates a headless run performs when it is not replaying.
+    pub ticks: u64,
+    /// Multiplier applied to the update delta before it reaches the game clock.
+    pub time_scale: f64,
+    /// Seed for the game RNG; a random one is picked and logged when absent.
+    pub seed: Option<u64>,
+    /// Write every tick's input to this file when the session ends.
+    pub record: Option<PathBuf>,
+    /// Drive the simulation from a recording instead of the window.
+    pub replay: Option<PathBuf>,
+    /// Id of the level in `assets/levels` to play.
+    pub level: Option<String>,
+    /// Let the AI act only after each move or attack of the player.
+    pub turn_mode: TurnMode,
+    /// How entities glide from tile to tile.
+    pub movement: MovementSettings,
+    /// Extra prefab instances, as `(prefab, (x, y))`, spawned on top of the level.
+    pub spawns: Vec<(String, (u64, u64))>,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            headless: false,
+            ticks: 600,
+            time_scale: 1.0,
+            seed: None,
+            record: None,
+            replay: None,
+            level: None,
+            turn_mode: TurnMode::RealTime,
+            movement: MovementSettings::default(),
+            spawns: Vec::new(),
+        }
+    }
+}
+
+impl GameConfig {
+    pub fn from_args() -> Self {
+        Self::parse(env::args().skip(1))
+    }
+
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Self {
+        let mut config = GameConfig::default();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--headless" => {
+                    config.headless = true;
+                }
+                "--ticks" => {
+                    config.ticks = args.next()
+                        .and_then(|v| v.parse().ok())
+                        .expect("--ticks expects a number of ticks");
+                }
+                "--record" => {
+                    config.record = Some(args.next()
+                        .map(PathBuf::from)
+                        .expect("--record expects a file path"));
+                }
+                "--replay" => {
+                    config.replay = Some(args.next()
+                        .map(PathBuf::from)
+                        .expect("--replay expects a file path"));
+                }
+                "--time-scale" => {
+                    config.time_scale = args.next()
+                        .and_then(|v| v.parse().ok())
+                        .expect("--time-scale expects a number");
+                }
+                "--seed" => {
+                    config.seed = Some(args.next()
+                        .and_then(|v| v.parse().ok())
+                        .expect("--seed expects an unsigned integer"));
+                }
+                "--level" => {
+                    config.level = Some(args.next()
+                        .expect("--level expects a level id"));
+                }
+                "--turn-based" => {
+                    config.turn_mode = TurnMode::TurnBased;
+                }
+                "--move-time" => {
+                    config.movement.duration = args.next()
+                        .and_then(|v| v.parse().ok())
+                        .and_then(|v| Duration::try_from_secs_f64(v).ok())
+                        .expect("--move-time expects a non-negative number of seconds");
+                }
+                "--easing" => {
+                    config.movement.easing = args.next()
+                        .and_then(|v| Easing::parse(&v))
+                        .expect("--easing expects linear, in, out or in-out");
+                }
+                "--spawn" => {
+                    config.spawns.push(args.next()
+                        .and_then(|v| Self::parse_spawn(&v))
+                        .expect("--spawn expects <prefab>:<x>,<y>"));
+                }
+                other => {
+                    eprintln!("Ignoring unknown argument: {}", other);
+                }
+            }
+        }
+
+        // Headless runs and replays have no window input to record.
+        assert!(config.record.is_none() || !config.headless, "--record cannot be combined with --headless");
+        assert!(config.record.is_none() || config.replay.is_none(), "--record cannot be combined with --replay");
+
+        config
+    }
+
+    fn parse_spawn(value: &str) -> Option<(String, (u64, u64))> {
+        let (name, coords) = value.split_once(':')?;
+        let (x, y) = coords.split_once(',')?;
+        Some((name.to_string(), (x.trim().parse().ok()?, y.trim().parse().ok()?)))
+    }
+}
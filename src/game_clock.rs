@@ -0,0 +1,82 @@
+use std::time::Duration;
+use piston_window::{Event, Loop};
+use specs::prelude::*;
+use crate::InputEvent;
+
+/// Simulation time, advanced only by the loop's update events.
+///
+/// Timed components store the `now()` they were created at and compare against it,
+/// so the same sequence of events always produces the same timings.
+#[derive(Debug)]
+pub struct GameClock {
+    now: Duration,
+    scale: f64,
+    paused: bool,
+}
+
+impl Default for GameClock {
+    fn default() -> Self {
+        Self {
+            now: Duration::ZERO,
+            scale: 1.0,
+            paused: false,
+        }
+    }
+}
+
+impl GameClock {
+    pub fn now(&self) -> Duration {
+        self.now
+    }
+
+    /// Moves the clock to `now`, used when restoring a saved game.
+    pub fn set_now(&mut self, now: Duration) {
+        self.now = now;
+    }
+
+    /// Advances the clock by `dt` seconds of real time, scaled by the current time scale.
+    pub fn advance(&mut self, dt: f64) {
+        self.now += Duration::from_secs_f64(self.scaled(dt));
+    }
+
+    /// Game time that passes during `dt` seconds of real time; none while paused.
+    pub fn scaled(&self, dt: f64) -> f64 {
+        if self.paused || dt <= 0.0 {
+            0.0
+        } else {
+            dt * self.scale
+        }
+    }
+
+    pub fn elapsed_since(&self, instant: Duration) -> Duration {
+        self.now.saturating_sub(instant)
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_scale(&mut self, scale: f64) {
+        self.scale = scale.max(0.0);
+    }
+}
+
+pub struct GameClockSys;
+
+impl<'a> System<'a> for GameClockSys {
+    type SystemData = (Read<'a, InputEvent>, Write<'a, GameClock>);
+
+    fn run(&mut self, (input, mut clock): Self::SystemData) {
+        if let Some(Event::Loop(Loop::Update(args))) = input.0 {
+            clock.advance(args.dt);
+        }
+    }
+}
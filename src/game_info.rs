@@ -1,10 +1,10 @@
-use piston_window::Key::Select;
+use serde::{Deserialize, Serialize};
 use specs::prelude::*;
 use crate::{BlobMarker, Health};
 
 pub struct GameInfoSys;
 
-#[derive(Default)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct GameInfo {
     pub blobs_health: Vec<u8>
 }
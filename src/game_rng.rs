@@ -0,0 +1,66 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use rand::{Isaac64Rng, Rng, SeedableRng};
+
+/// The single source of randomness for every system.
+///
+/// Systems draw from this resource instead of `rand::thread_rng()`, so a run can be
+/// reproduced exactly from its seed.
+pub struct GameRng {
+    seed: u64,
+    /// Values drawn since seeding, which is all it takes to restore the generator.
+    draws: u64,
+    rng: Isaac64Rng,
+}
+
+impl Default for GameRng {
+    fn default() -> Self {
+        GameRng::from_seed(0)
+    }
+}
+
+impl GameRng {
+    pub fn from_seed(seed: u64) -> Self {
+        Self {
+            seed,
+            draws: 0,
+            rng: Isaac64Rng::from_seed(&[seed][..]),
+        }
+    }
+
+    /// The generator seeded with `seed` after `draws` values were taken from it.
+    pub fn from_state(seed: u64, draws: u64) -> Self {
+        let mut rng = Self::from_seed(seed);
+        for _ in 0..draws {
+            rng.next_u64();
+        }
+        rng
+    }
+
+    /// Picks a seed from the system clock, for runs that were not given one.
+    pub fn random_seed() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default()
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn draws(&self) -> u64 {
+        self.draws
+    }
+}
+
+impl Rng for GameRng {
+    // Isaac64 makes a 32-bit value from a whole 64-bit one, so both count as one draw.
+    fn next_u32(&mut self) -> u32 {
+        self.draws += 1;
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.draws += 1;
+        self.rng.next_u64()
+    }
+}
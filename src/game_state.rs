@@ -0,0 +1,132 @@
+use specs::prelude::*;
+use crate::game_clock::GameClock;
+use crate::input_map::{Action, PlayerActions};
+use crate::rules_sys::{GameStats, Outcome};
+
+/// Which part of the game is being played.
+///
+/// The gameplay systems only run while `Playing`; everything else waits for a key.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum GameState {
+    #[default]
+    Menu,
+    Playing,
+    Paused,
+    GameOver,
+    Victory,
+}
+
+impl GameState {
+    pub fn is_playing(&self) -> bool {
+        *self == GameState::Playing
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(self, GameState::GameOver | GameState::Victory)
+    }
+}
+
+/// Set when the player asks to play a finished level again; the simulation rebuilds
+/// the world after the tick.
+#[derive(Default)]
+pub struct RestartRequested(pub bool);
+
+/// Moves between states on `Confirm` (start, play again) and `Pause` (pause, resume), and ends
+/// the game once `RulesSys` has decided its outcome.
+pub struct GameStateSys;
+
+impl<'a> System<'a> for GameStateSys {
+    type SystemData = (
+        Read<'a, PlayerActions>,
+        Write<'a, GameState>,
+        Write<'a, RestartRequested>,
+        Write<'a, GameClock>,
+        Read<'a, GameStats>,
+    );
+
+    fn run(&mut self, (actions, mut state, mut restart, mut clock, stats): Self::SystemData) {
+        for action in actions.iter() {
+            match (*state, action) {
+                (GameState::Menu, Action::Confirm) => *state = GameState::Playing,
+                (GameState::Playing, Action::Pause) => {
+                    clock.pause();
+                    *state = GameState::Paused;
+                }
+                (GameState::Paused, Action::Pause) => {
+                    clock.resume();
+                    *state = GameState::Playing;
+                }
+                (finished, Action::Confirm) if finished.is_finished() => restart.0 = true,
+                _ => {}
+            }
+        }
+
+        if state.is_playing() {
+            match stats.outcome {
+                Some(Outcome::Victory) => *state = GameState::Victory,
+                Some(Outcome::Defeat) => *state = GameState::GameOver,
+                None => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use piston_window::{Button, ButtonArgs, ButtonState, Event, Input, Key};
+    use crate::input_map::{InputMap, InputMapSys};
+    use crate::InputEvent;
+    use super::*;
+
+    fn world(state: GameState) -> World {
+        let mut world = World::new();
+        System::setup(&mut InputMapSys, &mut world);
+        System::setup(&mut GameStateSys, &mut world);
+        world.insert(InputMap::default());
+        world.insert(state);
+        world
+    }
+
+    /// Presses `key` for one tick and returns the state afterwards.
+    fn press(world: &mut World, key: Key) -> GameState {
+        let input = Input::Button(ButtonArgs { state: ButtonState::Press, button: Button::Keyboard(key), scancode: None });
+        world.insert(InputEvent(Some(Event::Input(input, None))));
+        InputMapSys.run_now(world);
+        GameStateSys.run_now(world);
+        *world.read_resource::<GameState>()
+    }
+
+    #[test]
+    fn confirm_starts_and_pause_toggles_the_game() {
+        let mut world = world(GameState::Menu);
+        assert_eq!(press(&mut world, Key::P), GameState::Menu);
+        assert_eq!(press(&mut world, Key::Return), GameState::Playing);
+        assert_eq!(press(&mut world, Key::P), GameState::Paused);
+        assert!(world.read_resource::<GameClock>().is_paused());
+        assert_eq!(press(&mut world, Key::Return), GameState::Paused);
+        assert_eq!(press(&mut world, Key::P), GameState::Playing);
+        assert!(!world.read_resource::<GameClock>().is_paused());
+    }
+
+    #[test]
+    fn the_outcome_ends_the_game() {
+        let mut world = world(GameState::Playing);
+        world.write_resource::<GameStats>().outcome = Some(Outcome::Defeat);
+        assert_eq!(press(&mut world, Key::Up), GameState::GameOver);
+
+        let mut world = self::world(GameState::Playing);
+        world.write_resource::<GameStats>().outcome = Some(Outcome::Victory);
+        assert_eq!(press(&mut world, Key::Up), GameState::Victory);
+    }
+
+    #[test]
+    fn confirm_after_the_game_is_over_requests_a_restart() {
+        for finished in [GameState::GameOver, GameState::Victory] {
+            let mut world = world(finished);
+            press(&mut world, Key::P);
+            assert!(!world.read_resource::<RestartRequested>().0);
+            press(&mut world, Key::Return);
+            assert!(world.read_resource::<RestartRequested>().0);
+        }
+    }
+}
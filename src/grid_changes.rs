@@ -1,32 +1,28 @@
 use std::sync::{Arc, Mutex};
-use nalgebra::Vector4;
 use piston_window::{Event, Input};
-use specs::{Entities, Join, Read, ReadStorage, System, Write, WriteStorage};
-use crate::{BlobMarker, Color, GridCoords, GridDimensions, InputEvent, PlayerMarker, Position};
-use crate::input_sys::ActionFired;
+use specs::{Read, System, Write};
+use crate::camera::Camera;
+use crate::{GridDimensions, InputEvent};
 
 pub struct GridChangesSys;
 
 impl<'a> System<'a> for GridChangesSys {
-    type SystemData = (Write<'a, Arc<Mutex<GridDimensions>>>,Read<'a, InputEvent>);
+    type SystemData = (Write<'a, Arc<Mutex<GridDimensions>>>,Read<'a, InputEvent>, Write<'a, Camera>);
 
-    fn run(&mut self, (grid_dims, input): Self::SystemData) {
-        if let Some(ev) = input.0.clone().map(|ev|ev) {
+    fn run(&mut self, (grid_dims, input, mut camera): Self::SystemData) {
+        if let Some(ev) = input.0.clone() {
             match ev {
                 Event::Input(inp, _) => {
-                    match inp {
-                        Input::Resize(rargs) => {
+                    if let Input::Resize(rargs) = inp {
 
-                            // println!("rargs.draw_size:{:?}", rargs.draw_size);
-                            // println!("rargs.window_size:{:?}", rargs.window_size);
-                            let mut g = grid_dims.lock().unwrap();
+                        // println!("rargs.draw_size:{:?}", rargs.draw_size);
+                        // println!("rargs.window_size:{:?}", rargs.window_size);
+                        let mut g = grid_dims.lock().unwrap();
 
-                            g.window_width = rargs.window_size[0];
-                            g.window_height = rargs.window_size[1];
+                        g.resize((rargs.window_size[0], rargs.window_size[1]));
+                        camera.set_viewport(g.board_size());
 
-                            // println!("Rows:{}, cols:{}", g.grid_rows(), g.grid_columns());
-                        },
-                        _ => {}
+                        // println!("Rows:{}, cols:{}", g.grid_rows(), g.grid_columns());
                     }
                 }
                 Event::Loop(_) => {}
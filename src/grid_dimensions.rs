@@ -0,0 +1,191 @@
+/// Where the grid lies in the world and how the window is split up.
+///
+/// World positions, as stored in `Position`, are in pixels with the grid's top-left
+/// corner at `ORIGIN`; a tile's position is its top-left corner. The window shows the
+/// board above a HUD of fixed height; the `Camera` maps the world onto the board.
+#[derive(Debug, Default, Clone)]
+pub struct GridDimensions {
+    window_size: (f64, f64),
+    columns: u64,
+    rows: u64,
+    tile_size: (f64, f64),
+}
+
+/// Offset of the grid in the world, so that grid lines fall on whole pixels.
+const ORIGIN: f64 = 0.5;
+
+/// Height of the HUD below the board, in tiles.
+const HUD_TILES: f64 = 1.5;
+
+impl GridDimensions {
+    /// A grid of `columns` by `rows` tiles of `tile_size` pixels, shown in a window of
+    /// `window_size` pixels; sizes are width first.
+    pub fn new(window_size: (f64, f64), (columns, rows): (u64, u64), tile_size: (f64, f64)) -> Self {
+        Self {
+            window_size,
+            columns,
+            rows,
+            tile_size,
+        }
+    }
+
+    /// Follows a change of the window size; the grid and all world positions stay put.
+    pub fn resize(&mut self, window_size: (f64, f64)) {
+        self.window_size = window_size;
+    }
+
+    pub fn window_size(&self) -> (f64, f64) {
+        self.window_size
+    }
+
+    pub fn tile_size(&self) -> (f64, f64) {
+        self.tile_size
+    }
+
+    pub fn grid_columns(&self) -> u64 {
+        self.columns
+    }
+
+    pub fn grid_rows(&self) -> u64 {
+        self.rows
+    }
+
+    pub fn contains(&self, (x, y): (u64, u64)) -> bool {
+        x < self.columns && y < self.rows
+    }
+
+    /// World position of the top-left corner of tile (`x`, `y`).
+    pub fn tile_to_world(&self, (x, y): (u64, u64)) -> (f64, f64) {
+        (ORIGIN + x as f64 * self.tile_size.0, ORIGIN + y as f64 * self.tile_size.1)
+    }
+
+    /// Tile covering the world position (`x`, `y`), if it is on the grid.
+    pub fn world_to_tile(&self, (x, y): (f64, f64)) -> Option<(u64, u64)> {
+        let column = ((x - ORIGIN) / self.tile_size.0).floor();
+        let row = ((y - ORIGIN) / self.tile_size.1).floor();
+        if !(column >= 0.0 && row >= 0.0) {
+            return None;
+        }
+        Some((column as u64, row as u64)).filter(|tile| self.contains(*tile))
+    }
+
+    /// World rectangle of tile (`x`, `y`) as `[x, y, width, height]`.
+    pub fn tile_rect(&self, tile: (u64, u64)) -> [f64; 4] {
+        let (x, y) = self.tile_to_world(tile);
+        [x, y, self.tile_size.0, self.tile_size.1]
+    }
+
+    /// World rectangle of the whole grid, however much of it the window shows.
+    pub fn map_rect(&self) -> [f64; 4] {
+        let (width, height) = self.map_size();
+        [ORIGIN, ORIGIN, width, height]
+    }
+
+    pub fn map_size(&self) -> (f64, f64) {
+        (self.columns as f64 * self.tile_size.0, self.rows as f64 * self.tile_size.1)
+    }
+
+    pub fn hud_height(&self) -> f64 {
+        self.tile_size.1 * HUD_TILES
+    }
+
+    /// Size of the window above the HUD, where the camera shows the map.
+    pub fn board_size(&self) -> (f64, f64) {
+        (self.window_size.0, (self.window_size.1 - self.hud_height()).max(0.0))
+    }
+
+    /// Window size that fits the whole grid plus the HUD.
+    pub fn fitting_window_size(&self) -> (f64, f64) {
+        let (width, height) = self.map_size();
+        (width, height + self.hud_height())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Shared with the other modules' tests that need a board.
+    pub(crate) fn grid() -> GridDimensions {
+        // Non-square tiles catch x and y being mixed up.
+        GridDimensions::new((640.0, 480.0), (12, 8), (40.0, 30.0))
+    }
+
+    #[test]
+    fn new_takes_width_before_height() {
+        let grid = grid();
+        assert_eq!(grid.window_size(), (640.0, 480.0));
+        assert_eq!(grid.grid_columns(), 12);
+        assert_eq!(grid.grid_rows(), 8);
+        assert_eq!(grid.tile_size(), (40.0, 30.0));
+    }
+
+    #[test]
+    fn tile_to_world_uses_each_axis_own_tile_size() {
+        let grid = grid();
+        assert_eq!(grid.tile_to_world((0, 0)), (0.5, 0.5));
+        assert_eq!(grid.tile_to_world((3, 2)), (120.5, 60.5));
+        assert_eq!(grid.tile_to_world((2, 3)), (80.5, 90.5));
+    }
+
+    #[test]
+    fn world_to_tile_inverts_tile_to_world() {
+        let grid = grid();
+        for x in 0..grid.grid_columns() {
+            for y in 0..grid.grid_rows() {
+                let (left, top) = grid.tile_to_world((x, y));
+                assert_eq!(grid.world_to_tile((left, top)), Some((x, y)));
+                assert_eq!(grid.world_to_tile((left + 39.9, top + 29.9)), Some((x, y)));
+            }
+        }
+    }
+
+    #[test]
+    fn world_to_tile_is_none_off_the_grid() {
+        let grid = grid();
+        assert_eq!(grid.world_to_tile((0.0, 10.0)), None);
+        assert_eq!(grid.world_to_tile((10.0, -5.0)), None);
+        assert_eq!(grid.world_to_tile((12.0 * 40.0 + 0.5, 10.0)), None);
+        assert_eq!(grid.world_to_tile((10.0, 8.0 * 30.0 + 0.5)), None);
+        assert_eq!(grid.world_to_tile((f64::NAN, 10.0)), None);
+    }
+
+    #[test]
+    fn tile_rect_and_map_rect() {
+        let grid = grid();
+        assert_eq!(grid.tile_rect((1, 2)), [40.5, 60.5, 40.0, 30.0]);
+        assert_eq!(grid.map_size(), (480.0, 240.0));
+        assert_eq!(grid.map_rect(), [0.5, 0.5, 480.0, 240.0]);
+    }
+
+    #[test]
+    fn contains_checks_both_bounds() {
+        let grid = grid();
+        assert!(grid.contains((0, 0)));
+        assert!(grid.contains((11, 7)));
+        assert!(!grid.contains((12, 0)));
+        assert!(!grid.contains((0, 8)));
+    }
+
+    #[test]
+    fn board_is_the_window_above_the_hud() {
+        let grid = grid();
+        assert_eq!(grid.hud_height(), 45.0);
+        assert_eq!(grid.board_size(), (640.0, 435.0));
+        assert_eq!(grid.fitting_window_size(), (480.0, 285.0));
+    }
+
+    #[test]
+    fn resize_changes_the_board_but_not_the_grid() {
+        let mut grid = grid();
+        grid.resize((320.0, 200.0));
+        assert_eq!(grid.board_size(), (320.0, 155.0));
+        assert_eq!(grid.grid_columns(), 12);
+        assert_eq!(grid.grid_rows(), 8);
+        assert_eq!(grid.tile_to_world((3, 2)), (120.5, 60.5));
+        assert_eq!(grid.world_to_tile((470.0, 230.0)), Some((11, 7)));
+
+        grid.resize((10.0, 20.0));
+        assert_eq!(grid.board_size(), (10.0, 0.0));
+    }
+}
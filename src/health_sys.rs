@@ -1,20 +1,31 @@
 use specs::prelude::*;
-use crate::{Damage, Death, Health};
+use crate::animation_sys::{AnimationEvents, AnimationState};
+use crate::rules_sys::GameStats;
+use crate::{Damage, Death, Health, PlayerMarker};
 
 pub struct HealthSys;
 
 impl<'a> System<'a> for HealthSys {
-    type SystemData = (Entities<'a>, WriteStorage<'a, Health>, WriteStorage<'a, Damage>, WriteStorage<'a, Death>);
+    type SystemData = (Entities<'a>, WriteStorage<'a, Health>, WriteStorage<'a, Damage>, WriteStorage<'a, Death>,
+                       ReadStorage<'a, PlayerMarker>, Write<'a, GameStats>, Write<'a, AnimationEvents>);
 
-    fn run(&mut self, (entities, mut health, mut damage, mut death): Self::SystemData) {
+    fn run(&mut self, (entities, mut health, mut damage, mut death, players, mut stats, mut animations): Self::SystemData) {
 
         let mut cleanup = Vec::new();
 
         for (entity, h, d) in (&entities, &mut health, &damage).join() {
+            let taken = d.0.min(h.0) as u32;
+            if players.contains(entity) {
+                stats.damage_taken += taken;
+            } else {
+                stats.damage_dealt += taken;
+            }
             h.reduce(d.0);
 
             if h.0 == 0 {
                 death.insert(entity, Death).unwrap();
+            } else {
+                animations.push(entity, AnimationState::Hurt);
             }
 
             cleanup.push(entity);
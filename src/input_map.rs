@@ -0,0 +1,399 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use piston_window::{Button, ButtonState, ControllerAxisArgs, Event, HatState, Input, Key, Motion, MouseButton};
+use serde::{Deserialize, Serialize};
+use specs::prelude::*;
+use crate::InputEvent;
+
+/// Something the player can ask for, independent of the button bound to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Attack,
+    Pause,
+    /// Starts the game from the menu and plays again once it is over.
+    Confirm,
+    QuickSave,
+    QuickLoad,
+    /// Selects the entity under the cursor, or walks to the tile under it.
+    Select,
+    PanUp,
+    PanDown,
+    PanLeft,
+    PanRight,
+    ZoomIn,
+    ZoomOut,
+    /// Centres the camera on the player again after panning.
+    FollowPlayer,
+}
+
+/// Buttons bound to each action, as read from `assets/input.ron`.
+///
+/// An action can have several buttons and a button can trigger several actions.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InputMap {
+    bindings: HashMap<Action, Vec<Button>>,
+    #[serde(default)]
+    gamepad: GamepadMap,
+}
+
+/// How the player's controller drives the same actions as the keyboard.
+///
+/// Buttons are given by number, so the bindings hold for whichever controller is used.
+/// The d-pad and the stick always move the player.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GamepadMap {
+    /// Controller the player uses; without one, the first controller pressed is taken.
+    #[serde(default)]
+    device: Option<u32>,
+    #[serde(default)]
+    buttons: HashMap<Action, Vec<u8>>,
+    /// Horizontal and vertical axis of the stick; positive is right and down.
+    #[serde(default = "GamepadMap::default_stick")]
+    stick: (u8, u8),
+    /// How far the stick has to be pushed, between 0 and 1, before it counts as a move.
+    #[serde(default = "GamepadMap::default_dead_zone")]
+    dead_zone: f64,
+}
+
+impl GamepadMap {
+    fn default_stick() -> (u8, u8) {
+        (0, 1)
+    }
+
+    fn default_dead_zone() -> f64 {
+        0.5
+    }
+
+    /// Whether `args` pushes the stick past the dead zone.
+    fn pushes_stick(&self, args: &ControllerAxisArgs) -> bool {
+        (args.axis == self.stick.0 || args.axis == self.stick.1) && args.position.abs() > self.dead_zone
+    }
+
+    fn actions(&self, button: u8) -> impl Iterator<Item = Action> + '_ {
+        self.buttons.iter()
+            .filter(move |(_, buttons)| buttons.contains(&button))
+            .map(|(action, _)| *action)
+    }
+}
+
+impl Default for GamepadMap {
+    /// Buttons numbered as SDL's game controllers: A attacks and Start pauses.
+    fn default() -> Self {
+        Self {
+            device: None,
+            buttons: HashMap::from([
+                (Action::Attack, vec![0]),
+                (Action::Pause, vec![6]),
+                (Action::Confirm, vec![0, 6]),
+            ]),
+            stick: Self::default_stick(),
+            dead_zone: Self::default_dead_zone(),
+        }
+    }
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        let bindings = [
+            (Action::MoveUp, Key::Up),
+            (Action::MoveDown, Key::Down),
+            (Action::MoveLeft, Key::Left),
+            (Action::MoveRight, Key::Right),
+            (Action::Attack, Key::D),
+            (Action::Pause, Key::P),
+            (Action::Confirm, Key::Return),
+            (Action::QuickSave, Key::F5),
+            (Action::QuickLoad, Key::F9),
+            (Action::PanUp, Key::I),
+            (Action::PanDown, Key::K),
+            (Action::PanLeft, Key::J),
+            (Action::PanRight, Key::L),
+            (Action::ZoomIn, Key::Equals),
+            (Action::ZoomOut, Key::Minus),
+            (Action::FollowPlayer, Key::F),
+        ];
+
+        let mut bindings = bindings.into_iter()
+            .map(|(action, key)| (action, vec![Button::Keyboard(key)]))
+            .collect::<HashMap<_, _>>();
+        bindings.insert(Action::Select, vec![Button::Mouse(MouseButton::Left)]);
+
+        Self {
+            bindings,
+            gamepad: GamepadMap::default(),
+        }
+    }
+}
+
+impl InputMap {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let contents = fs::read_to_string(path)?;
+        Ok(ron::from_str(&contents)?)
+    }
+
+    /// The buttons bound to `action`, as shown to the player, e.g. "Return or pad button 0".
+    pub fn describe(&self, action: Action) -> String {
+        let buttons = self.bindings.get(&action).into_iter().flatten()
+            .map(|button| match button {
+                Button::Keyboard(key) => format!("{:?}", key),
+                Button::Mouse(mouse) => format!("{:?} mouse button", mouse).to_lowercase(),
+                Button::Controller(button) => format!("pad button {}", button.button),
+                Button::Hat(hat) => format!("d-pad {:?}", hat.state).to_lowercase(),
+            });
+        let pad_buttons = self.gamepad.buttons.get(&action).into_iter().flatten()
+            .map(|button| format!("pad button {}", button));
+
+        let names = buttons.chain(pad_buttons).collect::<Vec<_>>();
+        if names.is_empty() {
+            format!("the {:?} button (unbound)", action)
+        } else {
+            names.join(" or ")
+        }
+    }
+
+    /// Actions bound to `button`.
+    pub fn actions(&self, button: Button) -> impl Iterator<Item = Action> + '_ {
+        self.bindings.iter()
+            .filter(move |(_, buttons)| buttons.contains(&button))
+            .map(|(action, _)| *action)
+    }
+}
+
+/// The controller driving the player and where its stick was last pushed.
+#[derive(Debug, Default)]
+pub struct GamepadState {
+    device: Option<u32>,
+    stick: (i8, i8),
+}
+
+impl GamepadState {
+    /// Whether controller `id` drives the player. With `claim`, it is taken if none has
+    /// been yet; only deliberate input claims, so a drifting stick does not.
+    fn accepts(&mut self, id: u32, map: &GamepadMap, claim: bool) -> bool {
+        match map.device {
+            Some(device) => device == id,
+            None if claim => *self.device.get_or_insert(id) == id,
+            None => self.device == Some(id),
+        }
+    }
+
+    /// The move for a stick pushed past the dead zone; holding it there moves only once.
+    fn stick_moved(&mut self, args: &ControllerAxisArgs, map: &GamepadMap) -> Option<Action> {
+        let direction = if args.position > map.dead_zone {
+            1
+        } else if args.position < -map.dead_zone {
+            -1
+        } else {
+            0
+        };
+
+        let (last, moves) = if args.axis == map.stick.0 {
+            (&mut self.stick.0, [Action::MoveLeft, Action::MoveRight])
+        } else if args.axis == map.stick.1 {
+            (&mut self.stick.1, [Action::MoveUp, Action::MoveDown])
+        } else {
+            return None;
+        };
+        if std::mem::replace(last, direction) == direction {
+            return None;
+        }
+        match direction {
+            -1 => Some(moves[0]),
+            1 => Some(moves[1]),
+            _ => None,
+        }
+    }
+}
+
+/// Actions the player triggered during this tick.
+#[derive(Debug, Default)]
+pub struct PlayerActions(Vec<Action>);
+
+impl PlayerActions {
+    pub fn contains(&self, action: Action) -> bool {
+        self.0.contains(&action)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Action> {
+        self.0.iter()
+    }
+}
+
+/// Last position of the mouse cursor in the window.
+#[derive(Debug, Default)]
+pub struct Cursor(pub Option<[f64; 2]>);
+
+/// Translates button presses and stick moves in the `InputEvent` into `PlayerActions`
+/// through the `InputMap`, before any other system looks at the input, and keeps track
+/// of the `Cursor`. Controllers other than the player's are ignored.
+pub struct InputMapSys;
+
+impl<'a> System<'a> for InputMapSys {
+    type SystemData = (Read<'a, InputEvent>, Read<'a, InputMap>, Write<'a, PlayerActions>, Write<'a, GamepadState>, Write<'a, Cursor>);
+
+    fn run(&mut self, (input, input_map, mut actions, mut gamepad, mut cursor): Self::SystemData) {
+        actions.0.clear();
+        let pad = &input_map.gamepad;
+
+        match &input.0 {
+            Some(Event::Input(Input::Button(args), _)) if args.state == ButtonState::Press => {
+                actions.0.extend(input_map.actions(args.button));
+                match args.button {
+                    Button::Controller(button) if gamepad.accepts(button.id, pad, true) => {
+                        actions.0.extend(pad.actions(button.button));
+                    }
+                    Button::Hat(hat) if gamepad.accepts(hat.id, pad, true) => {
+                        actions.0.extend(match hat.state {
+                            HatState::Up => Some(Action::MoveUp),
+                            HatState::Down => Some(Action::MoveDown),
+                            HatState::Left => Some(Action::MoveLeft),
+                            HatState::Right => Some(Action::MoveRight),
+                            _ => None,
+                        });
+                    }
+                    _ => {}
+                }
+            }
+            Some(Event::Input(Input::Move(Motion::ControllerAxis(args)), _)) if gamepad.accepts(args.id, pad, pad.pushes_stick(args)) => {
+                actions.0.extend(gamepad.stick_moved(args, pad));
+            }
+            Some(Event::Input(Input::Move(Motion::MouseCursor(position)), _)) => {
+                cursor.0 = Some(*position);
+            }
+            // The scroll wheel always zooms.
+            Some(Event::Input(Input::Move(Motion::MouseScroll([_, scroll])), _)) if *scroll != 0.0 => {
+                actions.0.push(if *scroll > 0.0 { Action::ZoomIn } else { Action::ZoomOut });
+            }
+            Some(Event::Input(Input::Cursor(false), _)) => {
+                cursor.0 = None;
+            }
+            _ => {}
+        }
+
+        actions.0.sort();
+        actions.0.dedup();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use piston_window::{ButtonArgs, ControllerButton, ControllerHat};
+    use super::*;
+
+    fn world(input_map: InputMap) -> World {
+        let mut world = World::new();
+        System::setup(&mut InputMapSys, &mut world);
+        world.insert(input_map);
+        world
+    }
+
+    /// Runs `InputMapSys` on `input` and returns the actions it produced.
+    fn actions(world: &mut World, input: Input) -> Vec<Action> {
+        world.insert(InputEvent(Some(Event::Input(input, None))));
+        InputMapSys.run_now(world);
+        world.read_resource::<PlayerActions>().iter().copied().collect()
+    }
+
+    fn press(button: Button) -> Input {
+        Input::Button(ButtonArgs { state: ButtonState::Press, button, scancode: None })
+    }
+
+    fn pad_button(id: u32, button: u8) -> Input {
+        press(Button::Controller(ControllerButton::new(id, button)))
+    }
+
+    fn hat(id: u32, state: HatState) -> Input {
+        press(Button::Hat(ControllerHat::new(id, 0, state)))
+    }
+
+    fn axis(id: u32, axis: u8, position: f64) -> Input {
+        Input::Move(Motion::ControllerAxis(ControllerAxisArgs::new(id, axis, position)))
+    }
+
+    #[test]
+    fn keys_map_to_their_actions() {
+        let mut world = world(InputMap::default());
+        assert_eq!(actions(&mut world, press(Button::Keyboard(Key::Up))), vec![Action::MoveUp]);
+        assert_eq!(actions(&mut world, press(Button::Keyboard(Key::Q))), vec![]);
+    }
+
+    #[test]
+    fn actions_are_described_by_their_buttons() {
+        let mut input_map = InputMap::default();
+        assert_eq!(input_map.describe(Action::Confirm), "Return or pad button 0 or pad button 6");
+        assert_eq!(input_map.describe(Action::Select), "left mouse button");
+
+        input_map.bindings.insert(Action::Pause, vec![Button::Keyboard(Key::Space)]);
+        input_map.gamepad.buttons.clear();
+        assert_eq!(input_map.describe(Action::Pause), "Space");
+        input_map.bindings.remove(&Action::Pause);
+        assert_eq!(input_map.describe(Action::Pause), "the Pause button (unbound)");
+    }
+
+    #[test]
+    fn controller_buttons_map_to_their_actions() {
+        let mut world = world(InputMap::default());
+        assert_eq!(actions(&mut world, pad_button(0, 0)), vec![Action::Attack, Action::Confirm]);
+        assert_eq!(actions(&mut world, pad_button(0, 6)), vec![Action::Pause, Action::Confirm]);
+        assert_eq!(actions(&mut world, pad_button(0, 3)), vec![]);
+    }
+
+    #[test]
+    fn hat_directions_move() {
+        let mut world = world(InputMap::default());
+        assert_eq!(actions(&mut world, hat(0, HatState::Up)), vec![Action::MoveUp]);
+        assert_eq!(actions(&mut world, hat(0, HatState::Down)), vec![Action::MoveDown]);
+        assert_eq!(actions(&mut world, hat(0, HatState::Left)), vec![Action::MoveLeft]);
+        assert_eq!(actions(&mut world, hat(0, HatState::Right)), vec![Action::MoveRight]);
+        assert_eq!(actions(&mut world, hat(0, HatState::Centered)), vec![]);
+        assert_eq!(actions(&mut world, hat(0, HatState::RightUp)), vec![]);
+    }
+
+    #[test]
+    fn stick_moves_once_per_push_past_the_dead_zone() {
+        let mut world = world(InputMap::default());
+        assert_eq!(actions(&mut world, axis(0, 0, 0.4)), vec![]);
+        assert_eq!(actions(&mut world, axis(0, 0, 0.9)), vec![Action::MoveRight]);
+        assert_eq!(actions(&mut world, axis(0, 0, 1.0)), vec![]);
+        assert_eq!(actions(&mut world, axis(0, 0, 0.1)), vec![]);
+        assert_eq!(actions(&mut world, axis(0, 0, 0.9)), vec![Action::MoveRight]);
+        assert_eq!(actions(&mut world, axis(0, 0, -0.9)), vec![Action::MoveLeft]);
+        assert_eq!(actions(&mut world, axis(0, 1, -0.9)), vec![Action::MoveUp]);
+        assert_eq!(actions(&mut world, axis(0, 1, 0.9)), vec![Action::MoveDown]);
+        assert_eq!(actions(&mut world, axis(0, 3, 0.9)), vec![]);
+    }
+
+    #[test]
+    fn first_controller_pressed_drives_the_player() {
+        let mut world = world(InputMap::default());
+        assert_eq!(actions(&mut world, pad_button(1, 0)), vec![Action::Attack, Action::Confirm]);
+        assert_eq!(actions(&mut world, pad_button(0, 0)), vec![]);
+        assert_eq!(actions(&mut world, hat(0, HatState::Up)), vec![]);
+        assert_eq!(actions(&mut world, axis(0, 0, 0.9)), vec![]);
+        assert_eq!(actions(&mut world, hat(1, HatState::Up)), vec![Action::MoveUp]);
+    }
+
+    #[test]
+    fn drifting_stick_does_not_claim_the_player() {
+        let mut world = world(InputMap::default());
+        assert_eq!(actions(&mut world, axis(0, 0, 0.2)), vec![]);
+        assert_eq!(actions(&mut world, axis(0, 4, 1.0)), vec![]);
+        assert_eq!(actions(&mut world, pad_button(1, 0)), vec![Action::Attack, Action::Confirm]);
+        assert_eq!(actions(&mut world, axis(0, 0, 0.9)), vec![]);
+    }
+
+    #[test]
+    fn configured_controller_is_the_only_one_accepted() {
+        let mut input_map = InputMap::default();
+        input_map.gamepad.device = Some(2);
+        let mut world = world(input_map);
+        assert_eq!(actions(&mut world, pad_button(0, 0)), vec![]);
+        assert_eq!(actions(&mut world, pad_button(2, 0)), vec![Action::Attack, Action::Confirm]);
+    }
+}
@@ -1,38 +1,32 @@
 use std::sync::{Arc, Mutex};
-use std::time::{Duration, Instant, SystemTime};
-use specs::{Component, Entities, Entity, Join, LazyUpdate, Read, ReadStorage, System, Write, WriteStorage};
-use piston_window::{Button, ButtonState, Event, Input, Key};
+use std::time::Duration;
+use specs::{Component, Entities, Entity, Join, LazyUpdate, Read, ReadStorage, System, WriteStorage};
 use specs::prelude::*;
-use crate::{GridCoords, GridDimensions, InputEvent, NewGridCoords, PlayerMarker, Position, Velocity};
-
-#[derive(Component, Debug)]
+use crate::game_clock::GameClock;
+use crate::input_map::{Action, PlayerActions};
+use crate::save_game::{PendingSaveAction, SaveAction};
+use crate::tile_map::TileMap;
+use crate::turn_sys::Turns;
+use crate::{Destination, Glide, GridCoords, GridDimensions, NewGridCoords, PlayerMarker, Position, Velocity};
+
+#[derive(Component, Debug, Default)]
 #[storage(VecStorage)]
 pub struct ActionFired{
     duration: Duration,
-    created: Instant,
+    created: Duration,
     handled: bool
 }
 
-impl Default for ActionFired {
-    fn default() -> Self {
-        Self {
-            duration:Duration::default(),
-            created: Instant::now(),
-            handled:false,
-        }
-    }
-}
-
 impl ActionFired {
-    pub fn new(duration: Duration) -> Self {
+    pub fn new(duration: Duration, clock: &GameClock) -> Self {
         Self {
             duration,
-            created: Instant::now(),
+            created: clock.now(),
             handled:false,
         }
     }
-    pub fn is_expired(&self) -> bool {
-        self.created.elapsed() > self.duration
+    pub fn is_expired(&self, clock: &GameClock) -> bool {
+        clock.elapsed_since(self.created) > self.duration
     }
 
     pub fn is_handled(&self) -> bool {
@@ -47,47 +41,36 @@ impl ActionFired {
 
 pub struct InputSys;
 impl<'a> System<'a> for InputSys {
-    type SystemData = (Entities<'a>, Read<'a, InputEvent>, ReadStorage<'a, Position>, WriteStorage<'a, Velocity>,
-        WriteStorage<'a, GridCoords>, ReadStorage<'a, PlayerMarker>, Read<'a, Arc<Mutex<GridDimensions>>>, Read<'a, LazyUpdate>,);
+    type SystemData = (Entities<'a>, Read<'a, PlayerActions>, ReadStorage<'a, Position>, WriteStorage<'a, Velocity>,
+        WriteStorage<'a, GridCoords>, ReadStorage<'a, PlayerMarker>, Read<'a, Arc<Mutex<GridDimensions>>>, Read<'a, LazyUpdate>,
+        Read<'a, GameClock>, Write<'a, PendingSaveAction>, Read<'a, TileMap>, Write<'a, Turns>, ReadStorage<'a, Glide>, WriteStorage<'a, Destination>);
 
-    fn run(&mut self, (mut entities, mut inp,pos, mut vs, mut grid_coords, player, grid_dims, updater): Self::SystemData) {
+    fn run(&mut self, (entities, actions, pos, mut vs, mut grid_coords, player, grid_dims, updater, clock, mut save_action, tile_map, mut turns, glides, mut destinations): Self::SystemData) {
 
         let mut coords_to_update = Vec::new();
-        if let Some((btn, btn_state)) =  inp.0.clone().map(|event| match event.clone() {
-            Event::Input(inp, _) => {
-                if let Input::Button(btn) = inp {
-                    Some((btn.button, btn.state))
-                } else {
-                    None
-                }
-
+        for action in actions.iter() {
+            match action {
+                Action::QuickSave => save_action.0 = Some(SaveAction::QuickSave),
+                Action::QuickLoad => save_action.0 = Some(SaveAction::QuickLoad),
+                _ => {}
             }
-            Event::Loop(_) => {None}
-            Event::Custom(_, _, _) => {None}
-        }).flatten() {
-
-
-
 
-            for (entity, _, v, grid_coord, _) in (&entities, &pos, &mut vs, &mut grid_coords, &player).join() {
-                if btn_state == ButtonState::Press {
-                match btn {
-                    Button::Keyboard(k) => {
-                        Self::add_location_update(&mut coords_to_update, entity, grid_coord, k.clone(), Arc::clone(&grid_dims));
+            // The player cannot act again before their last move has finished.
+            for (entity, _, _v, grid_coord, _, _) in (&entities, &pos, &mut vs, &mut grid_coords, &player, !&glides).join() {
+                Self::add_location_update(&mut coords_to_update, entity, grid_coord, *action, Arc::clone(&grid_dims), &tile_map);
 
-                        if let Key::D = k {
-                            updater.insert(entity, ActionFired::new(Duration::from_millis(100)))
-                        }
-                    }
-                    _ =>{}
-                }
-                } else {
-                    // v.x = 0.0;
-                    // v.y = 0.0;
+                if let Action::Attack = action {
+                    updater.insert(entity, ActionFired::new(Duration::from_millis(100), &clock));
+                    turns.end_player_turn();
                 }
             }
         }
 
+        // Moves only end the turn once `UpdatePos` accepts them.
+        // Moving by hand cancels a walk to a clicked tile.
+        for (entity, _) in &coords_to_update {
+            destinations.remove(*entity);
+        }
         for (entity, new_coord) in coords_to_update {
             // let stone = entities.create();
             updater.insert(entity, new_coord);
@@ -96,43 +79,20 @@ impl<'a> System<'a> for InputSys {
 }
 
 impl InputSys {
-    fn add_location_update(coords_to_update: &mut Vec<(Entity, NewGridCoords)>, entity: Entity, grid_coord: &GridCoords, k: Key, grid_dims: Arc<Mutex<GridDimensions>>) {
+    fn add_location_update(coords_to_update: &mut Vec<(Entity, NewGridCoords)>, entity: Entity, grid_coord: &GridCoords, action: Action,
+                           grid_dims: Arc<Mutex<GridDimensions>>, tile_map: &TileMap) {
         let (columns, rows)  = grid_dims.lock().map(|gd| (gd.grid_columns()-1, gd.grid_rows()-1)).unwrap();
 
-        match k {
-            Key::Right => {
-                if grid_coord.x + 1 <= columns {
-                    coords_to_update.push((entity, NewGridCoords {
-                        x: grid_coord.x + 1,
-                        y: grid_coord.y
-                    }))
-                }
-            }
-            Key::Left => {
-                if grid_coord.x > 0 {
-                    coords_to_update.push((entity, NewGridCoords {
-                        x: grid_coord.x - 1,
-                        y: grid_coord.y
-                    }))
-                }
-            }
-            Key::Down => {
-                if grid_coord.y + 1 <= rows {
-                    coords_to_update.push((entity, NewGridCoords {
-                        x: grid_coord.x,
-                        y: grid_coord.y + 1
-                    }))
-                }
-            }
-            Key::Up => {
-                if grid_coord.y > 0 {
-                    coords_to_update.push((entity, NewGridCoords {
-                        x: grid_coord.x,
-                        y: grid_coord.y - 1
-                    }))
-                }
-            }
-            _ => {}
+        let target = match action {
+            Action::MoveRight if grid_coord.x < columns => Some((grid_coord.x + 1, grid_coord.y)),
+            Action::MoveLeft if grid_coord.x > 0 => Some((grid_coord.x - 1, grid_coord.y)),
+            Action::MoveDown if grid_coord.y < rows => Some((grid_coord.x, grid_coord.y + 1)),
+            Action::MoveUp if grid_coord.y > 0 => Some((grid_coord.x, grid_coord.y - 1)),
+            _ => None,
+        };
+
+        if let Some((x, y)) = target.filter(|(x, y)| tile_map.is_walkable(*x, *y)) {
+            coords_to_update.push((entity, NewGridCoords { x, y }))
         }
     }
 }
@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use ai_behavior::Behavior;
+use serde::Deserialize;
+use specs::prelude::*;
+use crate::animation_sys::AnimationClip;
+use crate::behavior_sys::AIAction;
+use crate::prefabs::{check_placement, Prefab, PrefabRegistry};
+use crate::sprite_sheet::SpriteSheets;
+use crate::tile_map::TileMap;
+use crate::{Attacker, GridDimensions, Pursuer};
+
+/// Largest window opened for a level.
+const MAX_WINDOW_SIZE: (f64, f64) = (1000.0, 750.0);
+
+/// A level as described by a file in `assets/levels`.
+#[derive(Deserialize, Clone)]
+pub struct Level {
+    pub id: String,
+    pub grid: GridDef,
+    /// One string per row of the grid, see `Terrain::from_char`; empty means all floor.
+    #[serde(default)]
+    pub tiles: Vec<String>,
+    pub entities: Vec<EntityDef>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct GridDef {
+    pub columns: u64,
+    pub rows: u64,
+    pub tile_size: (f64, f64),
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub enum Marker {
+    Player,
+    AI,
+    Blob,
+}
+
+/// An entity placed in the level, either from a prefab or spelled out in full.
+///
+/// Components given next to a `prefab` override the prefab's own.
+#[derive(Deserialize, Clone)]
+pub struct EntityDef {
+    #[serde(default)]
+    pub prefab: Option<String>,
+    pub coords: (u64, u64),
+    #[serde(default)]
+    pub markers: Vec<Marker>,
+    #[serde(default)]
+    pub health: Option<u8>,
+    #[serde(default)]
+    pub color: Option<[f32; 4]>,
+    #[serde(default)]
+    pub sprite: Option<SpriteDef>,
+    #[serde(default)]
+    pub pursue: Option<Pursuer>,
+    #[serde(default)]
+    pub behavior: Option<Behavior<AIAction>>,
+    #[serde(default)]
+    pub attack: Option<Attacker>,
+}
+
+impl EntityDef {
+    pub fn overrides(&self) -> Prefab {
+        Prefab {
+            markers: self.markers.clone(),
+            health: self.health,
+            color: self.color,
+            sprite: self.sprite.clone(),
+            pursue: self.pursue.clone(),
+            behavior: self.behavior.clone(),
+            attack: self.attack.clone(),
+        }
+    }
+}
+
+/// A sprite, either spelled out in full or taken from a sheet in `assets/sprites`.
+///
+/// Anything given next to a `sheet` overrides what the sheet declares.
+#[derive(Deserialize, Debug, Clone)]
+pub struct SpriteDef {
+    #[serde(default)]
+    pub sheet: Option<String>,
+    #[serde(default)]
+    pub texture: String,
+    #[serde(default)]
+    pub current_frame: String,
+    #[serde(default)]
+    pub anchor: Option<(f64, f64)>,
+    #[serde(default)]
+    pub frames: HashMap<String, [f64; 4]>,
+    /// Animation clips by name, e.g. "idle", "walk" or "walk_left"; see `AnimationSys`.
+    #[serde(default)]
+    pub clips: HashMap<String, AnimationClip>,
+}
+
+impl SpriteDef {
+    /// Fills in whatever this sprite leaves out from its sheet, if it names one.
+    pub fn resolve(&mut self, sheets: &mut SpriteSheets) -> Result<(), Box<dyn Error>> {
+        let Some(name) = &self.sheet else {
+            return Ok(());
+        };
+        let sheet = sheets.get(name)?;
+
+        if self.texture.is_empty() {
+            self.texture = sheet.image.clone();
+        }
+        if self.current_frame.is_empty() {
+            self.current_frame = sheet.first_frame.clone();
+        }
+        self.anchor = self.anchor.or(Some(sheet.anchor));
+        for (frame, rect) in &sheet.frames {
+            self.frames.entry(frame.clone()).or_insert(*rect);
+        }
+        for (clip, frames) in &sheet.clips {
+            self.clips.entry(clip.clone()).or_insert_with(|| frames.clone());
+        }
+        Ok(())
+    }
+
+    /// Fails when the frame shown first or any frame of a clip is not among `frames`.
+    pub fn check_frames(&self) -> Result<(), Box<dyn Error>> {
+        if !self.frames.contains_key(&self.current_frame) {
+            return Err(format!("sprite starts on frame {:?}, which it has no rect for", self.current_frame).into());
+        }
+        for (name, clip) in &self.clips {
+            if let Some(missing) = clip.frames.iter().find(|f| !self.frames.contains_key(&f.frame)) {
+                return Err(format!("clip {} shows frame {:?}, which the sprite has no rect for", name, missing.frame).into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Centre of the first frame shown, the anchor of sprites that don't set one.
+    pub fn frame_center(&self) -> (f64, f64) {
+        self.frames.get(&self.current_frame)
+            .map_or((0.0, 0.0), |[_, _, width, height]| (width / 2.0, height / 2.0))
+    }
+}
+
+impl Level {
+    pub fn load(path: &Path, sheets: &mut SpriteSheets) -> Result<Self, Box<dyn Error>> {
+        let contents = fs::read_to_string(path)?;
+        let mut level: Level = ron::from_str(&contents)?;
+        for def in &mut level.entities {
+            if let Some(sprite) = def.sprite.as_mut() {
+                sprite.resolve(sheets)?;
+                sprite.check_frames()
+                    .map_err(|e| format!("Entity at {:?} in {}: {}", def.coords, path.display(), e))?;
+            }
+        }
+        Ok(level)
+    }
+
+    /// Window size that fits the whole grid plus the HUD below it, up to `MAX_WINDOW_SIZE`;
+    /// larger maps are scrolled through with the `Camera`.
+    pub fn window_size(&self) -> (u32, u32) {
+        let (width, height) = self.grid_dimensions().window_size();
+        (width as u32, height as u32)
+    }
+
+    /// The level's grid in a window of `window_size`.
+    pub fn grid_dimensions(&self) -> GridDimensions {
+        let mut grid_dimensions = GridDimensions::new((0.0, 0.0), (self.grid.columns, self.grid.rows), self.grid.tile_size);
+        let (width, height) = grid_dimensions.fitting_window_size();
+        grid_dimensions.resize((width.min(MAX_WINDOW_SIZE.0), height.min(MAX_WINDOW_SIZE.1)));
+        grid_dimensions
+    }
+
+    pub fn tile_map(&self) -> Result<TileMap, Box<dyn Error>> {
+        TileMap::from_layout(self.grid.columns, self.grid.rows, &self.tiles)
+    }
+
+    /// Creates every entity of the level in `world`, instantiating prefabs from `prefabs`.
+    /// The level's `TileMap` has to be in `world` already, so that placements can be checked.
+    pub fn populate(&self, world: &mut World, grid_dimensions: &GridDimensions, prefabs: &PrefabRegistry) -> Result<(), Box<dyn Error>> {
+        for def in &self.entities {
+            let overrides = def.overrides();
+            match &def.prefab {
+                Some(name) => {
+                    prefabs.instantiate(world, name, def.coords, &overrides, grid_dimensions)?;
+                }
+                None => {
+                    check_placement(world, def.coords, grid_dimensions)?;
+                    overrides.build(world.create_entity(), def.coords, grid_dimensions);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
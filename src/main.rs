@@ -1,30 +1,42 @@
 extern crate piston_window;
 
 use std::collections::HashMap;
-use std::ops::Deref;
-use std::path::PathBuf;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::sync::{Arc, Mutex};
-use std::time::{Duration, Instant};
+use std::time::Duration;
 use gfx_device_gl::{CommandBuffer, Factory, Resources};
 use nalgebra::Vector4;
 use piston_window::*;
-use specs::{Builder, Component, DispatcherBuilder, System, VecStorage, World, WorldExt};
+use specs::{Component, VecStorage};
 use specs::prelude::*;
 use specs::shred::Fetch;
+use serde::{Deserialize, Deserializer, Serialize};
 use sprite::{Sprite};
-use update_position_sys::UpdatePos;
-use crate::ai_sys::AISys;
-use crate::blob_interaction::BlobInteractionSys;
-use crate::cleanup_sys::CleanupSys;
-use crate::game_info::{GameInfo, GameInfoSys};
-use crate::grid_changes::GridChangesSys;
-use crate::health_sys::HealthSys;
-use crate::input_sys::{ActionFired, InputSys};
-use crate::sprite_movement_sys::SpriteMovementSys;
+use crate::animation_sys::{AnimationClip, Playback};
+use crate::camera::Camera;
+use crate::config::GameConfig;
+use crate::game_clock::GameClock;
+use crate::game_info::GameInfo;
+use crate::game_rng::GameRng;
+use crate::game_state::GameState;
+use crate::grid_dimensions::GridDimensions;
+use crate::input_map::{Action, InputMap};
+use crate::level::Level;
+use crate::pathfinding::Neighbourhood;
+use crate::pointer_sys::Selection;
+use crate::prefabs::{Prefab, PrefabRegistry};
+use crate::rules_sys::GameStats;
+use crate::replay::{InputRecorder, RecordedEvent, Recording};
+use crate::tile_map::TileMap;
+use crate::sprite_sheet::SpriteSheets;
+use crate::simulation::{update_event, Simulation, DEFAULT_LEVEL};
 
 
 mod input_sys;
+mod input_map;
 mod update_position_sys;
 mod collisions_sys;
 mod sprite_movement_sys;
@@ -34,17 +46,38 @@ mod cleanup_sys;
 mod health_sys;
 mod game_info;
 mod ai_sys;
-
-
-#[derive(Component, Debug, Default)]
+mod ai_attack_sys;
+mod behavior_sys;
+mod simulation;
+mod config;
+mod game_clock;
+mod game_state;
+mod rules_sys;
+mod turn_sys;
+mod movement_sys;
+mod animation_sys;
+mod game_rng;
+mod replay;
+mod level;
+mod prefabs;
+mod save_game;
+mod tile_map;
+mod sprite_sheet;
+mod pathfinding;
+mod grid_dimensions;
+mod pointer_sys;
+mod camera;
+
+
+#[derive(Component, Debug, Default, Clone, Serialize, Deserialize)]
 #[storage(NullStorage)]
 pub struct PlayerMarker;
 
-#[derive(Component, Debug, Default)]
+#[derive(Component, Debug, Default, Clone, Serialize, Deserialize)]
 #[storage(NullStorage)]
 pub struct AIMarker;
 
-#[derive(Component, Debug, Default)]
+#[derive(Component, Debug, Default, Clone, Serialize, Deserialize)]
 #[storage(NullStorage)]
 pub struct BlobMarker;
 
@@ -54,58 +87,52 @@ pub struct BlobMarker;
 pub struct CollisionMarker;
 
 
-#[derive(Component, Debug)]
+#[derive(Component, Debug, Default, Clone, Serialize, Deserialize)]
 #[storage(VecStorage)]
 pub struct ActionLock {
     duration: Duration,
-    created: Instant,
-}
-
-impl Default for ActionLock {
-    fn default() -> Self {
-        ActionLock::new(Duration::from_millis(0))
-    }
+    created: Duration,
 }
 
 impl ActionLock {
-    fn new(duration:Duration) -> Self {
+    fn new(duration:Duration, clock: &GameClock) -> Self {
         Self {
             duration,
-            created: Instant::now(),
+            created: clock.now(),
         }
     }
 
-    pub fn is_expired(&self) -> bool {
-        self.created.elapsed() > self.duration
+    pub fn is_expired(&self, clock: &GameClock) -> bool {
+        clock.elapsed_since(self.created) > self.duration
     }
 }
 
-#[derive(Component, Debug)]
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
 #[storage(VecStorage)]
 pub struct Position {
     x: f64,
     y: f64,
 }
 
-#[derive(Component, Debug)]
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
 #[storage(VecStorage)]
 pub struct Color(Vector4<f32>);
 
-#[derive(Component, Debug)]
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
 #[storage(VecStorage)]
 pub struct Velocity {
     pub x: f64,
     pub y: f64,
 }
 
-#[derive(Component, Debug, Default)]
+#[derive(Component, Debug, Default, Clone, Serialize, Deserialize)]
 #[storage(VecStorage)]
 pub struct GridCoords {
     pub x: u64,
     pub y: u64,
 }
 
-#[derive(Component, Debug)]
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
 #[storage(VecStorage)]
 pub struct Health(u8);
 
@@ -124,8 +151,16 @@ impl Health {
 #[storage(VecStorage)]
 pub struct Damage(u8);
 
+impl Damage {
+    /// Adds `amount` to the damage `target` takes this tick.
+    fn inflict(damage: &mut WriteStorage<Damage>, target: Entity, amount: u8) {
+        let total = damage.get(target).map_or(0, |d| d.0).saturating_add(amount);
+        damage.insert(target, Damage(total)).expect("Damage component added");
+    }
+}
 
-#[derive(Component, Debug, Default)]
+
+#[derive(Component, Debug, Default, Clone, Serialize, Deserialize)]
 #[storage(NullStorage)]
 pub struct Death;
 
@@ -148,115 +183,157 @@ pub struct NewGridCoords {
     pub y: u64,
 }
 
+/// An entity on its way from the position of its previous tile to that of its current
+/// one; see `MovementSys`.
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+#[storage(VecStorage)]
+pub struct Glide {
+    pub from: (f64, f64),
+    pub to: (f64, f64),
+    pub started: Duration,
+}
+
+/// A tile the player was sent to by clicking it, reached one step at a time; see `PointerSys`.
+#[derive(Component, Debug, Clone)]
+#[storage(VecStorage)]
+pub struct Destination {
+    pub x: u64,
+    pub y: u64,
+}
+
+/// Lets an AI entity hit the player next to it for `power`, then wait `cooldown` seconds.
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+#[storage(VecStorage)]
+pub struct Attacker {
+    pub power: u8,
+    #[serde(deserialize_with = "seconds")]
+    pub cooldown: f64,
+}
+
+/// Reads a length of time in seconds, refusing what `Duration` cannot hold.
+fn seconds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    let seconds = f64::deserialize(deserializer)?;
+    Duration::try_from_secs_f64(seconds)
+        .map(|_| seconds)
+        .map_err(|_| serde::de::Error::custom(format!("{} is not a valid number of seconds", seconds)))
+}
+
+/// Where an entity with a `Pursuer` is heading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PursuitTarget {
+    Player,
+    Tile(u64, u64),
+}
+
+/// Makes an AI entity walk along the shortest path to its target instead of wandering.
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+#[storage(VecStorage)]
+pub struct Pursuer {
+    pub target: PursuitTarget,
+    #[serde(default)]
+    pub neighbourhood: Neighbourhood,
+}
+
+
+/// Textures listed in `assets/textures.ron`, loaded before the first frame is drawn.
+#[derive(Deserialize)]
+struct TextureManifest {
+    #[serde(default)]
+    textures: Vec<String>,
+    /// Sprite sheets in `assets/sprites` whose images are loaded too.
+    #[serde(default)]
+    sheets: Vec<String>,
+}
 
+/// Creates sprites, loading each texture once and sharing it between all of them.
 pub struct SpriteFactory {
     assets: PathBuf,
-    texture_context: TextureContext<Factory, Resources, CommandBuffer>
+    texture_context: TextureContext<Factory, Resources, CommandBuffer>,
+    textures: HashMap<String, Rc<Texture<Resources>>>,
 }
 
 impl SpriteFactory {
     fn new(assets: PathBuf, window: &mut PistonWindow) -> Self {
 
 
-        let mut texture_context = TextureContext {
+        let texture_context = TextureContext {
             factory: window.factory.clone(),
             encoder: window.factory.create_command_buffer().into()
         };
         Self{
             assets,
-            texture_context
+            texture_context,
+            textures: HashMap::new(),
+        }
+    }
+
+    /// Loads every texture named in the manifest at `path`, relative to the assets folder.
+    fn preload(&mut self, path: &Path, sheets: &mut SpriteSheets) -> Result<(), Box<dyn Error>> {
+        let manifest: TextureManifest = ron::from_str(&fs::read_to_string(path)?)?;
+        for texture_name in &manifest.textures {
+            self.get_texture(texture_name)?;
+        }
+        for sheet in &manifest.sheets {
+            let image = sheets.get(sheet)?.image.clone();
+            self.get_texture(&image)?;
         }
+        Ok(())
     }
 
-    fn get_texture(&mut self, texture_name:&str) ->  Rc<Texture<Resources>> {
-        Rc::new(Texture::from_path(
+    fn get_texture(&mut self, texture_name:&str) -> Result<Rc<Texture<Resources>>, Box<dyn Error>> {
+        if let Some(texture) = self.textures.get(texture_name) {
+            return Ok(Rc::clone(texture));
+        }
+
+        let texture = Rc::new(Texture::from_path(
             &mut self.texture_context,
             self.assets.join(texture_name),
             Flip::None,
             &TextureSettings::new()
-        ).unwrap())
-    }
-
-    fn create_sprite(&mut self, texture_name: &str) -> Sprite<Texture<Resources>> {
-        Sprite::from_texture(Rc::clone(&self.get_texture(texture_name)))
+        ).map_err(|e| format!("Texture {} could not be loaded: {}", texture_name, e))?);
+        self.textures.insert(texture_name.to_string(), Rc::clone(&texture));
+        Ok(texture)
     }
 
-    fn create_sprite_from_rect(&mut self, texture_name: &str, rect: [f64; 4])-> Sprite<Texture<Resources>> {
-        Sprite::from_texture_rect(Rc::clone(&self.get_texture(texture_name)), rect)
+    fn create_sprite_from_rect(&mut self, texture_name: &str, rect: [f64; 4]) -> Result<Sprite<Texture<Resources>>, Box<dyn Error>> {
+        Ok(Sprite::from_texture_rect(self.get_texture(texture_name)?, rect))
     }
 }
 
 
 
-#[derive(Component, Debug, Default)]
+#[derive(Component, Debug, Default, Clone, Serialize, Deserialize)]
 #[storage(VecStorage)]
 pub struct PlayerSprite {
-    id: &'static str,
-    texture: &'static str,
-    current_frame: &'static str,
+    texture: String,
+    current_frame: String,
+    /// Point of the frame drawn on the centre of the entity's tile, in pixels from the
+    /// frame's top-left corner.
     anchor: (f64, f64),
-    frames: HashMap<&'static str, [f64; 4]>
+    frames: HashMap<String, [f64; 4]>,
+    /// Direction of the last move: "left", "right" or "vertical".
+    #[serde(default)]
+    facing: String,
+    #[serde(default)]
+    clips: HashMap<String, AnimationClip>,
+    #[serde(default)]
+    playback: Playback,
 }
 
 impl PlayerSprite {
-    fn update_frame(&mut self, new_frame:&'static str) {
-        self.current_frame = new_frame;
-    }
-}
-
-#[derive(Default)]
-pub struct GridDimensions {
-    window_height:f64,
-    window_width: f64,
-    // width: u64,
-    // height: u64,
-    tile_dims: (f64, f64),
-    tile_center: f64,
-}
-
-impl GridDimensions {
-    fn new(window_height:f64,
-           window_width: f64) -> Self {
-        Self {
-            window_height,
-            window_width,
-            tile_dims: (50.0, 50.0),
-            tile_center: 0.5,
+    fn update_frame(&mut self, new_frame:&str) {
+        if self.current_frame != new_frame {
+            self.current_frame = new_frame.to_string();
         }
     }
 
-
-
-    fn find_grid_position(&self, x:u64, y:u64) -> (f64, f64) {
-        (self.tile_center + x as f64 * self.tile_dims.0 / 2.0, self.tile_center + y as f64 * self.tile_dims.1 / 2.0)
-    }
-    fn find_position_for_gridx(&self, x:u64) -> (f64) {
-        self.tile_center + x  as f64 * self.tile_dims.0
-    }
-    fn find_position_for_gridy(&self, y:u64) -> (f64) {
-        self.tile_center + y  as f64 * self.tile_dims.1
-    }
-
-    fn tile_size(&self) -> (f64, f64) {
-        (self.tile_dims.0, self.tile_dims.1)
-    }
-
-    fn tile_center(&self) -> f64 {
-        self.tile_center
-    }
-
-    fn grid_columns(&self) -> u64 {
-        (self.window_width / self.tile_dims.0) as u64
-    }
-
-    fn grid_rows(&self) -> u64 {
-        ((self.window_height- self.tile_dims.1) / self.tile_dims.1) as u64
+    /// Turns the sprite; without animation clips the frame of that name is shown, if any.
+    fn face(&mut self, direction: &str) {
+        self.facing = direction.to_string();
+        if self.clips.is_empty() && self.frames.contains_key(direction) {
+            self.update_frame(direction);
+        }
     }
-
-}
-
-struct WorldDimensions {
-    grid: GridDimensions
 }
 
 // const GRID_WIDTH: u64 = 11;
@@ -269,157 +346,130 @@ struct WorldDimensions {
 #[derive(Default)]
 pub struct InputEvent(Option<Event>);
 
-// #[derive(Default)]
-// pub struct PositionsMap(HashMap<GridCoords, Entity>);
-
 fn main() {
-    let opengl = OpenGL::V3_2;
-    let (window_width, window_height) = (640, 480);
-    let mut grid_dimensions = GridDimensions::new(window_width as f64, window_height as f64);
-    let mut window: PistonWindow = WindowSettings::new("Hello Piston!", [window_width, window_height])
-        .exit_on_esc(true).graphics_api(opengl).build().unwrap();
+    let config = GameConfig::from_args();
 
     let assets = find_folder::Search::ParentsThenKids(3, 3)
         .for_folder("assets").unwrap();
 
-    let mut sprite_factory = SpriteFactory::new(assets.clone(), &mut window);
+    let replay = config.replay.as_ref()
+        .map(|path| Recording::load(path).expect("Replay file could not be read"));
+    let seed = replay.as_ref().map(|r| r.seed)
+        .or(config.seed)
+        .unwrap_or_else(GameRng::random_seed);
+    println!("RNG seed: {}", seed);
+    let level_id = replay.as_ref().map(|r| r.level.clone())
+        .or_else(|| config.level.clone())
+        .unwrap_or_else(|| DEFAULT_LEVEL.to_string());
+    let mut sheets = SpriteSheets::new(assets.join("sprites"));
+    let level = Level::load(&assets.join("levels").join(format!("{}.ron", level_id)), &mut sheets)
+        .expect("Level could not be loaded");
+    let prefabs = PrefabRegistry::load(&assets.join("prefabs.ron"), &mut sheets)
+        .expect("Prefabs could not be loaded");
+    let mut simulation = Simulation::new(&level, prefabs, seed);
+    let spawns = replay.as_ref().map(|r| r.spawns.clone())
+        .unwrap_or_else(|| config.spawns.clone());
+    for (name, coords) in &spawns {
+        simulation.spawn(name, *coords, &Prefab::default())
+            .expect("Prefab could not be spawned");
+    }
+    let time_scale = replay.as_ref().map(|r| r.time_scale).unwrap_or(config.time_scale);
+    simulation.clock_mut().set_scale(time_scale);
+    let turn_mode = replay.as_ref().map(|r| r.turn_mode).unwrap_or(config.turn_mode);
+    simulation.set_turn_mode(turn_mode);
+    let movement = replay.as_ref().map(|r| r.movement).unwrap_or(config.movement);
+    simulation.set_movement(movement);
+    let input_map = match replay.as_ref().and_then(|r| r.input_map.clone()) {
+        Some(input_map) => input_map,
+        None => InputMap::load(&assets.join("input.ron")).expect("Input map could not be loaded"),
+    };
+    simulation.set_input_map(input_map.clone());
+
+    if config.headless {
+        match replay {
+            // Recordings start in the menu like the window does.
+            Some(recording) => run_headless(&mut simulation, recording.events.iter().map(RecordedEvent::to_event)),
+            None => {
+                simulation.start();
+                run_headless(&mut simulation, (0..config.ticks).map(|_| update_event(1.0 / 60.0)))
+            }
+        }
+        return;
+    }
 
+    let mut recorder = config.record.clone()
+        .map(|path| InputRecorder::new(path, Recording {
+            seed,
+            level: simulation.level().to_string(),
+            spawns: spawns.clone(),
+            turn_mode,
+            time_scale,
+            movement,
+            input_map: Some(input_map),
+            events: Vec::new(),
+        }));
+    let mut replay_events = replay.map(|r| r.events.into_iter());
+
+    let (window_width, window_height) = level.window_size();
+    let opengl = OpenGL::V3_2;
+    let mut window: PistonWindow = WindowSettings::new("Hello Piston!", [window_width, window_height])
+        .exit_on_esc(true).graphics_api(opengl).build().unwrap();
 
+    let mut sprite_factory = SpriteFactory::new(assets.clone(), &mut window);
+    sprite_factory.preload(&assets.join("textures.ron"), &mut sheets)
+        .expect("Textures could not be preloaded");
 
-    let mut world = World::new();
-    world.register::<Position>();
-    world.register::<Velocity>();
-    world.register::<GridCoords>();
-    world.register::<PlayerMarker>();
-    world.register::<AIMarker>();
-    world.register::<BlobMarker>();
-    world.register::<NewGridCoords>();
-    world.register::<PlayerSprite>();
-    world.register::<ActionFired>();
-    world.register::<Color>();
-    world.register::<Health>();
-    world.register::<Damage>();
-    world.register::<Death>();
-    world.register::<ActionLock>();
-    world.insert::<InputEvent>(InputEvent(None));
-    // world.insert::<PositionsMap>(PositionsMap(HashMap::new()));
-
-    // Only the second entity will get a position update,
-    // because the first one does not have a velocity.
-    // world.create_entity().with(Position { x: 4.0, y: 7.0 }).build();
-    world
-        .create_entity()
-        .with(PlayerMarker{})
-        .with(PlayerSprite{
-            id: "player",
-            texture: "person2.png",
-            current_frame: "vertical",
-            anchor: (64.0/2.0, 64.0/2.0),
-            frames: [("vertical", [0.0, 0.0, 64.0, 64.0]),
-                ("left", [64.0,0.0,64.0,64.0]),
-                ("right", [0.0,64.0,64.0,64.0])].iter().cloned().collect()
-        })
-        .with(Position { x: grid_dimensions.find_position_for_gridx(0), y: grid_dimensions.find_position_for_gridy(0) })
-        .with(Velocity { x: 0.0, y: 0.0 })
-        .with(GridCoords {x: 0, y:0})
-        .with(Health(100))
-        .build();
-
-
-    world
-        .create_entity()
-        .with(AIMarker{})
-        .with(PlayerSprite{
-            id: "ai1",
-            texture: "person2.png",
-            current_frame: "vertical",
-            anchor: (64.0/2.0, 64.0/2.0),
-            frames: [("vertical", [0.0, 0.0, 64.0, 64.0]),
-                ("left", [64.0,0.0,64.0,64.0]),
-                ("right", [0.0,64.0,64.0,64.0])].iter().cloned().collect()
-        })
-        .with(Position { x: grid_dimensions.find_position_for_gridx(4), y: grid_dimensions.find_position_for_gridy(4) })
-        .with(Velocity { x: 0.0, y: 0.0 })
-        .with(GridCoords {x: 4, y:4})
-        .with(Health(100))
-        .build();
-
-
-    world
-        .create_entity()
-        .with(BlobMarker{})
-        .with(Position { x: grid_dimensions.find_position_for_gridx(3), y: grid_dimensions.find_position_for_gridy(3) })
-        .with(Velocity { x: 0.0, y: 0.0 })
-        .with(GridCoords {x: 3, y:3})
-        .with(Health(100))
-        .with(Color(Vector4::new(0.0, 1.0, 0.0, 1.0)))
-        .build();
-
-
-    world
-        .create_entity()
-        .with(BlobMarker{})
-        .with(Position { x: grid_dimensions.find_position_for_gridx(0), y: grid_dimensions.find_position_for_gridy(1) })
-        .with(Velocity { x: 0.0, y: 0.0 })
-        .with(GridCoords {x: 0, y:1})
-        .with(Health(100))
-        .with(Color(Vector4::new(0.0, 1.0, 0.0, 1.0)))
-        .build();
-
-    let grid_dimensions = Arc::new(Mutex::new(grid_dimensions));
-    world.insert::<Arc<Mutex<GridDimensions>>>(Arc::clone(&grid_dimensions));
-    // world.insert::<Instant>(Instant::now());
-    world.insert::<GameInfo>(GameInfo::default());
-
-
-    let mut dispatcher = DispatcherBuilder::new()
-        .with(InputSys, "input", &[])
-        .with(AISys, "ai_sys", &["input"])
-        .with(SpriteMovementSys, "sprite_updates", &["input", "ai_sys"])
-        .with(UpdatePos, "update_pos", &["input"])
-        .with(BlobInteractionSys, "blob_interaction", &["update_pos"])
-        .with(HealthSys, "health", &["blob_interaction"])
-        .with(GridChangesSys, "grid_changes", &["input"])
-        .with(CleanupSys, "cleanup", &["input", "grid_changes"])
-        .with(GameInfoSys, "game_info", &["cleanup"])
-        .build();
-
-    let mut step = 0;
+    let grid_dimensions = simulation.grid_dimensions();
 
     let mut sprite_map = HashMap::new();
-
-    // initialize all sprites
-    {
-        let sprite_components = world.read_storage::<PlayerSprite>();
-        let positions = world.read_storage::<Position>();
-
-
-        for (sprite, pos) in (&sprite_components, &positions).join() {
-            println!("Sprite! {:?}", pos);
-            let mut s = sprite_factory.create_sprite_from_rect(sprite.texture, sprite.frames.get(&sprite.current_frame).unwrap().clone());
-            s.set_position(pos.x, pos.y);
-            s.set_anchor(0.0, 0.0);
-            sprite_map.insert(sprite.id, s);
-        }
-    }
+    let mut resets = simulation.resets();
 
     let mut glyphs = window.load_font(assets.join("FiraSans-Regular.ttf")).unwrap();
 
 
     while let Some(event) = window.next() {
-        world.insert::<InputEvent>(InputEvent(Some(event.clone())));
-
-        dispatcher.dispatch(&mut world);
-        world.maintain();
-
+        match replay_events.as_mut() {
+            // While replaying, window input is ignored and each frame advances the
+            // recording up to and including its next render tick.
+            Some(events) => {
+                if event.render_args().is_some() {
+                    for recorded in events.by_ref() {
+                        simulation.step(recorded.to_event());
+                        if recorded.is_render() {
+                            break;
+                        }
+                    }
+                }
+            }
+            None => {
+                simulation.step(event.clone());
+                if let Some(recorder) = recorder.as_mut() {
+                    recorder.record(&event);
+                }
+            }
+        }
 
-        // println!("time: {:?}", start.elapsed());
+        let world = simulation.world();
 
+        // Sprites belong to entities of the world they were made for.
+        if simulation.resets() != resets {
+            resets = simulation.resets();
+            sprite_map.clear();
+        }
+        {
+            let sprites = world.read_storage::<PlayerSprite>();
+            sprite_map.retain(|entity, _| world.is_alive(*entity) && sprites.contains(*entity));
+        }
 
         window.draw_2d(&event, |context, graphics, _| {
             clear([1.0, 1.0, 1.0, 1.0], graphics);
 
-            draw_grid(context, graphics, Arc::clone(&grid_dimensions));
+            // The map is drawn in world positions through the camera; text stays unscaled.
+            let camera = world.read_resource::<Camera>();
+            let board = Context { transform: camera.transform(context.transform), ..context };
+
+            draw_tiles(board, graphics, &world.read_resource::<TileMap>(), Arc::clone(&grid_dimensions));
+            draw_grid(board, graphics, Arc::clone(&grid_dimensions));
 
 
 
@@ -433,11 +483,15 @@ fn main() {
             let game_info = world.read_resource::<GameInfo>();
 
 
-            let tile_dims = grid_dimensions.lock().unwrap().tile_dims.clone();
+            let tile_dims = grid_dimensions.lock().unwrap().tile_size();
 
 
 
-            draw_text_box(game_info, &mut glyphs, &context, graphics, Arc::clone(&grid_dimensions));
+            let selection = world.read_resource::<Selection>();
+            if let Some(pos) = selection.0.and_then(|entity| positions.get(entity)) {
+                Rectangle::new_border([1.0, 0.8, 0.0, 1.0], 2.0)
+                    .draw([pos.x, pos.y, tile_dims.0, tile_dims.1], &board.draw_state, board.transform, graphics);
+            }
 
             let mut overlays = Vec::new();
 
@@ -446,21 +500,38 @@ fn main() {
                 // println!("pos: x:{}, y:{}", pos.x, pos.y);
                 ellipse([color.0[0], color.0[1], color.0[2], color.0[3]], // Red color
                         [pos.x, pos.y, tile_dims.0, tile_dims.1], // x, y, width, height
-                        context.transform, graphics);
+                        board.transform, graphics);
 
                 overlays.push((format!("H: {}", h.0), pos.x, pos.y));
             }
 
-            for (pos, sprite, h) in (&positions, &sprite_components, &health).join() {
-                let mut s = sprite_map.get_mut(&sprite.id).unwrap();
-
-                s.set_src_rect(sprite.frames.get(&sprite.current_frame).unwrap().clone());
-                s.set_position(pos.x,  pos.y);
+            // Sprites are created the first time their entity is drawn, so entities
+            // spawned at runtime get one too. An entity whose texture fails to load is
+            // reported once and not drawn.
+            for (entity, pos, sprite, h) in (&world.entities(), &positions, &sprite_components, &health).join() {
+                let s = sprite_map.entry(entity).or_insert_with(|| {
+                    match sprite_factory.create_sprite_from_rect(&sprite.texture, *sprite.frames.get(&sprite.current_frame).unwrap()) {
+                        Ok(s) => Some(s),
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            None
+                        }
+                    }
+                });
+                let Some(s) = s else {
+                    continue;
+                };
+
+                let rect = *sprite.frames.get(&sprite.current_frame).unwrap();
+                s.set_src_rect(rect);
+                s.set_anchor(sprite.anchor.0 / rect[2], sprite.anchor.1 / rect[3]);
+                s.set_position(pos.x + tile_dims.0 / 2.0, pos.y + tile_dims.1 / 2.0);
                 overlays.push((format!("H: {}", h.0), pos.x, pos.y));
-                s.draw(context.transform, graphics);
+                s.draw(board.transform, graphics);
             }
 
             for (text, x, y) in overlays {
+                let [x, y] = camera.world_to_screen((x, y));
                 text::Text::new_color([0.0, 0.0, 0.0, 1.0], 13).draw(
                     text.as_str(),
                     &mut glyphs,
@@ -470,6 +541,12 @@ fn main() {
                 ).unwrap();
             }
 
+            // The HUD goes on top of whatever part of the map scrolled under it.
+            draw_text_box(game_info, selection.details(world), &mut glyphs, &context, graphics, Arc::clone(&grid_dimensions));
+
+            draw_state_screen(simulation.state(), &world.read_resource::<GameStats>(), &world.read_resource::<InputMap>(),
+                              &mut glyphs, &context, graphics);
+
         });
 
         glyphs.factory.encoder.flush(&mut window.device);
@@ -477,19 +554,48 @@ fn main() {
     }
 }
 
-fn draw_text_box(game_info: Fetch<GameInfo>, mut glyphs: &mut Glyphs, context: &Context, graphics: &mut G2d, grid_dims:Arc<Mutex<GridDimensions>>) {
+/// Drives the simulation with `events`, one per tick, without opening a window.
+fn run_headless(simulation: &mut Simulation, events: impl Iterator<Item = Event>) {
+    for event in events {
+        simulation.step(event);
+    }
+
+    let world = simulation.world();
+    let game_info = world.read_resource::<GameInfo>();
+    let coords = world.read_storage::<GridCoords>();
+    let players = world.read_storage::<PlayerMarker>();
+    let ais = world.read_storage::<AIMarker>();
+    let health = world.read_storage::<Health>();
+
+    println!("Ran {} ticks headless on {} with seed {}, ending {:?}", simulation.ticks(), simulation.level(), simulation.seed(), simulation.state());
+    for (c, _, h) in (&coords, &players, health.maybe()).join() {
+        println!("Player at ({}, {}) with health {}", c.x, c.y, h.map_or(0, |h| h.0));
+    }
+    for (c, _) in (&coords, &ais).join() {
+        println!("AI at ({}, {})", c.x, c.y);
+    }
+    println!("Blobs health: {:?}", game_info.blobs_health);
+    if let Some(selected) = world.read_resource::<Selection>().details(world) {
+        println!("Selected {}", selected);
+    }
+
+    let stats = world.read_resource::<GameStats>();
+    println!("Damage dealt {}, taken {}", stats.damage_dealt, stats.damage_taken);
+    if let Some(outcome) = stats.outcome {
+        println!("{:?} after {:.1} s", outcome, stats.elapsed.as_secs_f64());
+    }
+}
+
+fn draw_text_box(game_info: Fetch<GameInfo>, selected: Option<String>, glyphs: &mut Glyphs, context: &Context, graphics: &mut G2d, grid_dims:Arc<Mutex<GridDimensions>>) {
     let grid_dims = grid_dims.lock().unwrap();
     let tile_size = grid_dims.tile_size();
-    let tile_center = grid_dims.tile_center();
-    let windows_height = grid_dims.window_height;
-    let windows_width = grid_dims.window_width;
+    let (windows_width, windows_height) = grid_dims.window_size();
 
-    let text_pane_height = tile_size.1 * 1.5;
+    let text_pane_height = grid_dims.hud_height();
 
     let text_transform = context.transform.trans(10.0, windows_height - text_pane_height);
     rectangle([0.0, 0.0, 0.0, 1.0], [0.0, 0.0, windows_width - tile_size.0, text_pane_height], text_transform, graphics);
 
-    let section_width = 150.0;
     text::Text::new_color([1.0, 1.0, 1.0, 1.0], 12).draw(
         format!("Alive blobs: {}", game_info.blobs_health.iter().filter(|h|**h > 0).count()).as_str(),
         glyphs,
@@ -497,6 +603,15 @@ fn draw_text_box(game_info: Fetch<GameInfo>, mut glyphs: &mut Glyphs, context: &
         text_transform.trans(tile_size.0, 24.0), graphics
     ).unwrap();
 
+    if let Some(selected) = selected {
+        text::Text::new_color([1.0, 1.0, 1.0, 1.0], 12).draw(
+            format!("Selected: {}", selected).as_str(),
+            glyphs,
+            &context.draw_state,
+            text_transform.trans(tile_size.0 * 4.0, 24.0), graphics
+        ).unwrap();
+    }
+
 
     // text::Text::new_color([1.0, 1.0, 1.0, 1.0], 12).draw(
     //     "Hello world2!",
@@ -506,20 +621,67 @@ fn draw_text_box(game_info: Fetch<GameInfo>, mut glyphs: &mut Glyphs, context: &
     // ).unwrap();
 }
 
+/// Covers the board with the menu, pause or result screen; nothing while playing. The
+/// hint names whatever buttons `input_map` binds to leave the screen.
+fn draw_state_screen(state: GameState, stats: &GameStats, input_map: &InputMap, glyphs: &mut Glyphs, context: &Context, graphics: &mut G2d) {
+    let (background, title, action, purpose) = match state {
+        GameState::Playing => return,
+        GameState::Menu => ([1.0, 1.0, 1.0, 1.0], "Blobs", Action::Confirm, "start"),
+        GameState::Paused => ([0.0, 0.0, 0.0, 0.6], "Paused", Action::Pause, "resume"),
+        GameState::GameOver => ([0.0, 0.0, 0.0, 0.6], "Game over", Action::Confirm, "play again"),
+        GameState::Victory => ([0.0, 0.0, 0.0, 0.6], "Victory!", Action::Confirm, "play again"),
+    };
+    let hint = format!("Press {} to {}", input_map.describe(action), purpose);
+    let text_color = if state == GameState::Menu { [0.0, 0.0, 0.0, 1.0] } else { [1.0, 1.0, 1.0, 1.0] };
+
+    let mut lines = vec![(title.to_string(), 32)];
+    if state.is_finished() {
+        lines.push((format!("Time: {:.1} s", stats.elapsed.as_secs_f64()), 16));
+        lines.push((format!("Damage dealt: {}", stats.damage_dealt), 16));
+        lines.push((format!("Damage taken: {}", stats.damage_taken), 16));
+    }
+    lines.push((hint, 16));
+
+    let [width, height] = context.get_view_size();
+    rectangle(background, [0.0, 0.0, width, height], context.transform, graphics);
+
+    let top = height / 2.0 - 20.0 * lines.len() as f64;
+    for (i, (line, size)) in lines.iter().enumerate() {
+        // Hints vary with the bindings, so lines are centred on their measured width.
+        let line_width = glyphs.width(*size, line).unwrap_or(240.0);
+        text::Text::new_color(text_color, *size).draw(
+            line,
+            glyphs,
+            &context.draw_state,
+            context.transform.trans((width - line_width) / 2.0, top + i as f64 * 40.0),
+            graphics
+        ).unwrap();
+    }
+}
+
+fn draw_tiles(context: Context, graphics: &mut G2d, tile_map: &TileMap, grid_dims:Arc<Mutex<GridDimensions>>) {
+    let grid_dims = grid_dims.lock().unwrap();
+    for (x, y, terrain) in tile_map.tiles() {
+        if let Some(color) = terrain.color() {
+            rectangle(color, grid_dims.tile_rect((x, y)), context.transform, graphics);
+        }
+    }
+}
+
 fn draw_grid(context: Context, graphics: &mut G2d, grid_dims:Arc<Mutex<GridDimensions>>) {
     let grid_dims = grid_dims.lock().unwrap();
     let tile_size = grid_dims.tile_size();
-    let tile_center = grid_dims.tile_center();
-    for row in 0..grid_dims.grid_rows() + 2 {
-        let i = ((row as f64) * tile_size.1) + tile_center;
-        line_from_to([1.0, 0.5, 1.0, 1.0], 1.0, [tile_center, i], [tile_size.1 * (grid_dims.grid_columns() as f64 + 1.1), i],
+    let [left, top, map_width, map_height] = grid_dims.map_rect();
+    for row in 0..=grid_dims.grid_rows() {
+        let i = ((row as f64) * tile_size.1) + top;
+        line_from_to([1.0, 0.5, 1.0, 1.0], 1.0, [left, i], [left + map_width, i],
                      context.transform, graphics);
     }
 
 
-    for row in 0..grid_dims.grid_columns() + 2 {
-        let i = ((row as f64) * tile_size.0) + tile_center;
-        line_from_to([1.0, 0.5, 1.0, 1.0], 1.0, [i, tile_center], [i, tile_size.0 * (grid_dims.grid_rows() as f64 + 1.1)],
+    for column in 0..=grid_dims.grid_columns() {
+        let i = ((column as f64) * tile_size.0) + left;
+        line_from_to([1.0, 0.5, 1.0, 1.0], 1.0, [i, top], [i, top + map_height],
                      context.transform, graphics);
     }
 }
@@ -0,0 +1,136 @@
+use std::time::Duration;
+use piston_window::{Event, Loop};
+use serde::{Deserialize, Serialize};
+use specs::prelude::*;
+use crate::game_clock::GameClock;
+use crate::{Glide, InputEvent, Position, Velocity};
+
+/// Shape of the curve an entity follows between two tiles.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    #[default]
+    EaseInOut,
+}
+
+impl Easing {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "linear" => Some(Easing::Linear),
+            "in" => Some(Easing::EaseIn),
+            "out" => Some(Easing::EaseOut),
+            "in-out" => Some(Easing::EaseInOut),
+            _ => None,
+        }
+    }
+
+    /// Fraction of the way covered after `t` of the time, both between 0 and 1.
+    pub fn apply(&self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+/// How long a step from tile to tile takes and how it is eased.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct MovementSettings {
+    /// Time a move to a neighbouring tile takes; zero moves instantly.
+    pub duration: Duration,
+    pub easing: Easing,
+}
+
+impl Default for MovementSettings {
+    fn default() -> Self {
+        Self {
+            duration: Duration::from_millis(150),
+            easing: Easing::default(),
+        }
+    }
+}
+
+/// Moves every gliding entity's `Position` along its eased path on each update, keeping
+/// its `Velocity` at the speed it covered during the update. The glide is removed once
+/// the target is reached.
+pub struct MovementSys;
+
+impl<'a> System<'a> for MovementSys {
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, InputEvent>,
+        Read<'a, GameClock>,
+        Read<'a, MovementSettings>,
+        WriteStorage<'a, Glide>,
+        WriteStorage<'a, Position>,
+        WriteStorage<'a, Velocity>,
+    );
+
+    fn run(&mut self, (entities, input, clock, settings, mut glides, mut positions, mut velocities): Self::SystemData) {
+        let dt = match input.0 {
+            Some(Event::Loop(Loop::Update(args))) => clock.scaled(args.dt),
+            _ => return,
+        };
+
+        let mut finished = Vec::new();
+        for (entity, glide, pos, velocity) in (&entities, &glides, &mut positions, &mut velocities).join() {
+            let elapsed = clock.elapsed_since(glide.started).as_secs_f64();
+            let t = if settings.duration.is_zero() { 1.0 } else { elapsed / settings.duration.as_secs_f64() };
+            let progress = settings.easing.apply(t);
+
+            let x = glide.from.0 + (glide.to.0 - glide.from.0) * progress;
+            let y = glide.from.1 + (glide.to.1 - glide.from.1) * progress;
+            if dt > 0.0 {
+                velocity.x = (x - pos.x) / dt;
+                velocity.y = (y - pos.y) / dt;
+            }
+            pos.x = x;
+            pos.y = y;
+
+            if t >= 1.0 {
+                velocity.x = 0.0;
+                velocity.y = 0.0;
+                finished.push(entity);
+            }
+        }
+
+        for entity in finished {
+            glides.remove(entity);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [Easing; 4] = [Easing::Linear, Easing::EaseIn, Easing::EaseOut, Easing::EaseInOut];
+
+    #[test]
+    fn easings_start_at_zero_and_end_at_one() {
+        for easing in ALL {
+            assert_eq!(easing.apply(0.0), 0.0, "{:?}", easing);
+            assert_eq!(easing.apply(1.0), 1.0, "{:?}", easing);
+        }
+    }
+
+    #[test]
+    fn easings_clamp_times_outside_the_move() {
+        for easing in ALL {
+            assert_eq!(easing.apply(-0.5), 0.0, "{:?}", easing);
+            assert_eq!(easing.apply(2.0), 1.0, "{:?}", easing);
+        }
+    }
+
+    #[test]
+    fn easings_parse_from_their_cli_names() {
+        let names = ["linear", "in", "out", "in-out"];
+        assert_eq!(names.map(|name| Easing::parse(name).unwrap()), ALL);
+        assert_eq!(Easing::parse("bounce"), None);
+    }
+}
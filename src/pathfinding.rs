@@ -0,0 +1,344 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use serde::{Deserialize, Serialize};
+use specs::Entity;
+use crate::GridDimensions;
+
+pub type Tile = (u64, u64);
+
+/// Which tiles count as adjacent when searching.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Neighbourhood {
+    #[default]
+    Four,
+    Eight,
+}
+
+impl Neighbourhood {
+    /// Lower bound of the number of steps between two tiles.
+    pub fn distance(&self, a: Tile, b: Tile) -> u64 {
+        let dx = a.0.abs_diff(b.0);
+        let dy = a.1.abs_diff(b.1);
+        match self {
+            Neighbourhood::Four => dx + dy,
+            Neighbourhood::Eight => dx.max(dy),
+        }
+    }
+}
+
+/// The area a search runs over: a `columns` x `rows` grid and which of its tiles can be entered.
+pub struct SearchGrid<F: Fn(u64, u64) -> bool> {
+    columns: u64,
+    rows: u64,
+    passable: F,
+    neighbourhood: Neighbourhood,
+}
+
+impl<F: Fn(u64, u64) -> bool> SearchGrid<F> {
+    pub fn new(grid_dimensions: &GridDimensions, neighbourhood: Neighbourhood, passable: F) -> Self {
+        Self {
+            columns: grid_dimensions.grid_columns(),
+            rows: grid_dimensions.grid_rows(),
+            passable,
+            neighbourhood,
+        }
+    }
+
+    fn contains(&self, (x, y): Tile) -> bool {
+        x < self.columns && y < self.rows
+    }
+
+    /// Passable neighbours of `tile`, in a fixed order. Diagonal steps are only allowed
+    /// when both tiles they cut past are passable too.
+    fn neighbours(&self, (x, y): Tile) -> Vec<Tile> {
+        let (x, y) = (x as i64, y as i64);
+        let open = |dx: i64, dy: i64| {
+            let (nx, ny) = (x + dx, y + dy);
+            nx >= 0 && ny >= 0 && self.contains((nx as u64, ny as u64)) && (self.passable)(nx as u64, ny as u64)
+        };
+
+        let mut result = Vec::new();
+        for (dx, dy) in [(0, -1), (1, 0), (0, 1), (-1, 0)] {
+            if open(dx, dy) {
+                result.push(((x + dx) as u64, (y + dy) as u64));
+            }
+        }
+        if self.neighbourhood == Neighbourhood::Eight {
+            for (dx, dy) in [(1, -1), (1, 1), (-1, 1), (-1, -1)] {
+                if open(dx, dy) && open(dx, 0) && open(0, dy) {
+                    result.push(((x + dx) as u64, (y + dy) as u64));
+                }
+            }
+        }
+        result
+    }
+
+    /// Shortest path from `start` to `goal` by A*.
+    ///
+    /// The path excludes `start` and ends with `goal`; `goal` itself does not need to be
+    /// passable, so a path can lead up to an occupied tile. Ties are broken on the
+    /// remaining distance and then the tile coordinates, so the same inputs always give
+    /// the same path.
+    pub fn a_star(&self, start: Tile, goal: Tile) -> Option<Vec<Tile>> {
+        if !self.contains(start) || !self.contains(goal) {
+            return None;
+        }
+
+        let mut came_from = HashMap::new();
+        let mut cost = HashMap::new();
+        let mut open = BinaryHeap::new();
+        cost.insert(start, 0u64);
+        let h = self.neighbourhood.distance(start, goal);
+        open.push(Reverse((h, h, start)));
+
+        while let Some(Reverse((_, _, tile))) = open.pop() {
+            if tile == goal {
+                return Some(Self::rebuild_path(&came_from, start, goal));
+            }
+            let tile_cost = cost[&tile];
+            for next in self.neighbours_towards(tile, goal) {
+                let next_cost = tile_cost + 1;
+                if cost.get(&next).is_none_or(|c| next_cost < *c) {
+                    cost.insert(next, next_cost);
+                    came_from.insert(next, tile);
+                    let h = self.neighbourhood.distance(next, goal);
+                    open.push(Reverse((next_cost + h, h, next)));
+                }
+            }
+        }
+        None
+    }
+
+    fn neighbours_towards(&self, tile: Tile, goal: Tile) -> Vec<Tile> {
+        let mut neighbours = self.neighbours(tile);
+        if self.neighbourhood.distance(tile, goal) == 1 && !neighbours.contains(&goal) {
+            let (dx, dy) = (goal.0.abs_diff(tile.0), goal.1.abs_diff(tile.1));
+            // An impassable goal can still be reached, as long as the step itself is legal.
+            if dx + dy == 1 || (self.neighbourhood == Neighbourhood::Eight
+                && (self.passable)(goal.0, tile.1) && (self.passable)(tile.0, goal.1)) {
+                neighbours.push(goal);
+            }
+        }
+        neighbours
+    }
+
+    /// Summary of which tiles are passable, to tell whether anything changed since a search.
+    fn fingerprint(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        (self.columns, self.rows).hash(&mut hasher);
+        for y in 0..self.rows {
+            for x in 0..self.columns {
+                (self.passable)(x, y).hash(&mut hasher);
+            }
+        }
+        hasher.finish()
+    }
+
+    fn rebuild_path(came_from: &HashMap<Tile, Tile>, start: Tile, goal: Tile) -> Vec<Tile> {
+        let mut path = vec![goal];
+        let mut tile = goal;
+        while let Some(&previous) = came_from.get(&tile) {
+            if previous == start {
+                break;
+            }
+            path.push(previous);
+            tile = previous;
+        }
+        path.reverse();
+        path
+    }
+}
+
+enum CachedPath {
+    Found {
+        goal: Tile,
+        steps: VecDeque<Tile>,
+    },
+    /// No path led from `start` to `goal` while the passable tiles had `fingerprint`.
+    Unreachable {
+        start: Tile,
+        goal: Tile,
+        fingerprint: u64,
+    },
+}
+
+/// Remembers each agent's path between ticks so it is only searched again when the goal
+/// moves or the path gets blocked. A goal found unreachable is not searched for again
+/// until the goal, the agent or the passable tiles change.
+#[derive(Default)]
+pub struct PathCache {
+    paths: HashMap<Entity, CachedPath>,
+}
+
+impl PathCache {
+    /// Next tile `entity` should step on to get from `start` to `goal`, searching with
+    /// A* only when the cached path is missing, stale or blocked.
+    pub fn next_step<F: Fn(u64, u64) -> bool>(&mut self, entity: Entity, start: Tile, goal: Tile, grid: &SearchGrid<F>) -> Option<Tile> {
+        let reusable = match self.paths.get_mut(&entity) {
+            Some(CachedPath::Found { goal: cached_goal, steps }) => {
+                if steps.front() == Some(&start) {
+                    steps.pop_front();
+                }
+                *cached_goal == goal
+                    && steps.front().is_some_and(|next| {
+                        grid.neighbourhood.distance(start, *next) == 1 && (*next == goal || (grid.passable)(next.0, next.1))
+                    })
+            }
+            Some(CachedPath::Unreachable { start: cached_start, goal: cached_goal, fingerprint }) => {
+                if (*cached_start, *cached_goal, *fingerprint) == (start, goal, grid.fingerprint()) {
+                    return None;
+                }
+                false
+            }
+            None => false,
+        };
+
+        if !reusable {
+            let cached = match grid.a_star(start, goal) {
+                Some(path) => CachedPath::Found { goal, steps: path.into() },
+                None => CachedPath::Unreachable { start, goal, fingerprint: grid.fingerprint() },
+            };
+            self.paths.insert(entity, cached);
+        }
+
+        match self.paths.get(&entity) {
+            Some(CachedPath::Found { steps, .. }) => steps.front().copied(),
+            _ => None,
+        }
+    }
+
+    /// Drops the paths of entities for which `keep` returns false.
+    pub fn retain(&mut self, keep: impl Fn(Entity) -> bool) {
+        self.paths.retain(|entity, _| keep(*entity));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+    use specs::{Builder, World, WorldExt};
+    use super::*;
+
+    /// A 5x4 board with one unit per tile.
+    fn dimensions() -> GridDimensions {
+        GridDimensions::new((0.0, 0.0), (5, 4), (1.0, 1.0))
+    }
+
+    fn search_grid(neighbourhood: Neighbourhood, walls: &[Tile]) -> SearchGrid<impl Fn(u64, u64) -> bool + '_> {
+        SearchGrid::new(&dimensions(), neighbourhood, move |x, y| !walls.contains(&(x, y)))
+    }
+
+    fn entity() -> Entity {
+        World::new().create_entity().build()
+    }
+
+    #[test]
+    fn path_excludes_start_and_ends_at_goal() {
+        let grid = search_grid(Neighbourhood::Four, &[]);
+        let path = grid.a_star((0, 0), (3, 0)).unwrap();
+        assert_eq!(path, vec![(1, 0), (2, 0), (3, 0)]);
+    }
+
+    #[test]
+    fn path_goes_around_walls() {
+        let walls = [(1, 0), (1, 1), (1, 2)];
+        let grid = search_grid(Neighbourhood::Four, &walls);
+        let path = grid.a_star((0, 0), (2, 0)).unwrap();
+        assert_eq!(path.len(), 8);
+        assert!(path.iter().all(|tile| !walls.contains(tile)));
+        assert_eq!(path.last(), Some(&(2, 0)));
+    }
+
+    #[test]
+    fn unreachable_goal_has_no_path() {
+        let walls = [(1, 0), (1, 1), (1, 2), (1, 3)];
+        let grid = search_grid(Neighbourhood::Four, &walls);
+        assert_eq!(grid.a_star((0, 0), (4, 3)), None);
+        assert_eq!(grid.a_star((0, 0), (9, 9)), None);
+    }
+
+    #[test]
+    fn eight_neighbourhood_steps_diagonally() {
+        let grid = search_grid(Neighbourhood::Eight, &[]);
+        assert_eq!(grid.a_star((0, 0), (2, 2)), Some(vec![(1, 1), (2, 2)]));
+    }
+
+    #[test]
+    fn diagonal_steps_do_not_cut_corners() {
+        let grid = search_grid(Neighbourhood::Eight, &[(1, 0)]);
+        assert_eq!(grid.a_star((0, 0), (1, 1)), Some(vec![(0, 1), (1, 1)]));
+
+        let grid = search_grid(Neighbourhood::Eight, &[(0, 1)]);
+        assert_eq!(grid.a_star((0, 0), (1, 1)), Some(vec![(1, 0), (1, 1)]));
+    }
+
+    #[test]
+    fn impassable_goal_can_be_reached() {
+        let grid = search_grid(Neighbourhood::Four, &[(2, 0)]);
+        assert_eq!(grid.a_star((0, 0), (2, 0)), Some(vec![(1, 0), (2, 0)]));
+
+        // Diagonally only when the step would not cut a corner.
+        let grid = search_grid(Neighbourhood::Eight, &[(1, 1)]);
+        assert_eq!(grid.a_star((0, 0), (1, 1)), Some(vec![(1, 1)]));
+        let grid = search_grid(Neighbourhood::Eight, &[(1, 1), (1, 0)]);
+        assert_eq!(grid.a_star((0, 0), (1, 1)), Some(vec![(0, 1), (1, 1)]));
+    }
+
+    #[test]
+    fn ties_are_broken_the_same_way_every_time() {
+        let grid = search_grid(Neighbourhood::Four, &[]);
+        let path = grid.a_star((0, 0), (2, 2)).unwrap();
+        assert_eq!(path, vec![(0, 1), (0, 2), (1, 2), (2, 2)]);
+        for _ in 0..10 {
+            assert_eq!(grid.a_star((0, 0), (2, 2)).unwrap(), path);
+        }
+    }
+
+    #[test]
+    fn cache_follows_the_path_and_searches_again_when_the_goal_moves() {
+        let grid = search_grid(Neighbourhood::Four, &[]);
+        let mut cache = PathCache::default();
+        let entity = entity();
+
+        assert_eq!(cache.next_step(entity, (0, 0), (3, 0), &grid), Some((1, 0)));
+        assert_eq!(cache.next_step(entity, (1, 0), (3, 0), &grid), Some((2, 0)));
+        assert_eq!(cache.next_step(entity, (2, 0), (2, 3), &grid), Some((2, 1)));
+    }
+
+    #[test]
+    fn cache_searches_again_when_the_path_gets_blocked() {
+        let walls = RefCell::new(Vec::new());
+        let dimensions = dimensions();
+        let grid = SearchGrid::new(&dimensions, Neighbourhood::Four, |x, y| !walls.borrow().contains(&(x, y)));
+        let mut cache = PathCache::default();
+        let entity = entity();
+
+        assert_eq!(cache.next_step(entity, (0, 0), (3, 0), &grid), Some((1, 0)));
+        walls.borrow_mut().push((2, 0));
+        assert_eq!(cache.next_step(entity, (1, 0), (3, 0), &grid), Some((1, 1)));
+    }
+
+    #[test]
+    fn cache_keeps_an_unreachable_goal_until_the_map_changes() {
+        let walls = RefCell::new(vec![(1, 0), (1, 1), (1, 2), (1, 3)]);
+        let checks = Cell::new(0);
+        let dimensions = dimensions();
+        let grid = SearchGrid::new(&dimensions, Neighbourhood::Four, |x, y| {
+            checks.set(checks.get() + 1);
+            !walls.borrow().contains(&(x, y))
+        });
+        let mut cache = PathCache::default();
+        let entity = entity();
+
+        assert_eq!(cache.next_step(entity, (0, 0), (4, 0), &grid), None);
+
+        // Only the passable tiles are looked at, not searched through again.
+        checks.set(0);
+        assert_eq!(cache.next_step(entity, (0, 0), (4, 0), &grid), None);
+        assert_eq!(checks.get(), 5 * 4);
+
+        walls.borrow_mut().retain(|wall| *wall != (1, 3));
+        assert_eq!(cache.next_step(entity, (0, 0), (4, 0), &grid), Some((0, 1)));
+    }
+}
@@ -0,0 +1,117 @@
+use std::sync::{Arc, Mutex};
+use specs::prelude::*;
+use crate::camera::Camera;
+use crate::collisions_sys::PositionsMap;
+use crate::input_map::{Action, Cursor, PlayerActions};
+use crate::pathfinding::{Neighbourhood, PathCache, SearchGrid};
+use crate::tile_map::TileMap;
+use crate::{AIMarker, BlobMarker, Death, Destination, Glide, GridCoords, GridDimensions, Health, NewGridCoords, PlayerMarker};
+
+/// The entity last clicked on; it is highlighted and described in the HUD.
+#[derive(Debug, Default)]
+pub struct Selection(pub Option<Entity>);
+
+impl Selection {
+    /// One line about the selected entity for the HUD.
+    pub fn details(&self, world: &World) -> Option<String> {
+        let entity = self.0?;
+        let kind = if world.read_storage::<PlayerMarker>().contains(entity) {
+            "Player"
+        } else if world.read_storage::<AIMarker>().contains(entity) {
+            "AI"
+        } else if world.read_storage::<BlobMarker>().contains(entity) {
+            "Blob"
+        } else {
+            "Entity"
+        };
+        let coords = world.read_storage::<GridCoords>().get(entity).cloned()?;
+        let health = world.read_storage::<Health>().get(entity).map_or(0, |h| h.0);
+
+        Some(format!("{} at ({}, {}), health {}", kind, coords.x, coords.y, health))
+    }
+}
+
+/// Acts on `Select` at the cursor and walks the player to a clicked tile.
+///
+/// Clicking an entity selects it; clicking a free tile clears the selection and gives the
+/// player a `Destination`, which is then approached one step per finished move along the
+/// shortest path. The walk ends at the tile, when no path is left, or on a manual move.
+pub struct PointerSys;
+
+impl<'a> System<'a> for PointerSys {
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, PlayerActions>,
+        Read<'a, Cursor>,
+        Read<'a, Camera>,
+        Read<'a, Arc<Mutex<GridDimensions>>>,
+        Read<'a, TileMap>,
+        Read<'a, PositionsMap>,
+        ReadStorage<'a, GridCoords>,
+        ReadStorage<'a, PlayerMarker>,
+        ReadStorage<'a, Death>,
+        ReadStorage<'a, Glide>,
+        WriteStorage<'a, Destination>,
+        WriteStorage<'a, NewGridCoords>,
+        Write<'a, Selection>,
+        Write<'a, PathCache>,
+    );
+
+    fn run(&mut self, (entities, actions, cursor, camera, grid_dims, tile_map, positions_map, coords, player, death, glides,
+                       mut destinations, mut new_coords, mut selection, mut path_cache): Self::SystemData) {
+        let grid_dims = grid_dims.lock().unwrap();
+
+        if selection.0.is_some_and(|entity| !entities.is_alive(entity) || death.contains(entity)) {
+            selection.0 = None;
+        }
+
+        let clicked = cursor.0
+            .filter(|_| actions.contains(Action::Select))
+            .and_then(|point| camera.screen_to_world(point))
+            .and_then(|world| grid_dims.world_to_tile(world));
+        if let Some(tile) = clicked {
+            let occupant = (&entities, &coords, !&death).join()
+                .find(|(_, c, _)| (c.x, c.y) == tile)
+                .map(|(entity, _, _)| entity);
+
+            match occupant {
+                Some(entity) => selection.0 = Some(entity),
+                None if tile_map.is_walkable(tile.0, tile.1) => {
+                    selection.0 = None;
+                    for (entity, _, _) in (&entities, &player, !&death).join() {
+                        destinations.insert(entity, Destination { x: tile.0, y: tile.1 }).expect("Destination component added");
+                    }
+                }
+                None => {}
+            }
+        }
+
+        // A step is only taken once the previous one has finished.
+        let mut steps = Vec::new();
+        let mut arrived = Vec::new();
+        for (entity, destination, coord, _) in (&entities, &destinations, &coords, !&glides).join() {
+            let start = (coord.x, coord.y);
+            let goal = (destination.x, destination.y);
+            if start == goal {
+                arrived.push(entity);
+                continue;
+            }
+
+            let free = |x: u64, y: u64| tile_map.is_walkable(x, y) && positions_map.is_free_for(x, y, entity);
+            let search = SearchGrid::new(&grid_dims, Neighbourhood::Four, free);
+            match path_cache.next_step(entity, start, goal, &search) {
+                Some(next) if free(next.0, next.1) => steps.push((entity, next)),
+                // Someone stands on the destination itself; wait for them to leave.
+                Some(_) => {}
+                None => arrived.push(entity),
+            }
+        }
+
+        for (entity, (x, y)) in steps {
+            new_coords.insert(entity, NewGridCoords { x, y }).expect("NewGridCoords component added");
+        }
+        for entity in arrived {
+            destinations.remove(entity);
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use ai_behavior::Behavior;
+use nalgebra::Vector4;
+use serde::Deserialize;
+use specs::prelude::*;
+use specs::saveload::MarkedBuilder;
+use crate::animation_sys::Playback;
+use crate::behavior_sys::{AIAction, AIBehavior};
+use crate::level::{Marker, SpriteDef};
+use crate::save_game::SaveMarkers;
+use crate::sprite_sheet::SpriteSheets;
+use crate::tile_map::TileMap;
+use crate::{AIMarker, Attacker, BlobMarker, Color, Death, GridCoords, GridDimensions, Health, PlayerMarker, PlayerSprite, Position, Pursuer, Velocity};
+
+/// Default components of a named archetype.
+#[derive(Deserialize, Clone, Default)]
+pub struct Prefab {
+    #[serde(default)]
+    pub markers: Vec<Marker>,
+    #[serde(default)]
+    pub health: Option<u8>,
+    #[serde(default)]
+    pub color: Option<[f32; 4]>,
+    #[serde(default)]
+    pub sprite: Option<SpriteDef>,
+    #[serde(default)]
+    pub pursue: Option<Pursuer>,
+    #[serde(default)]
+    pub behavior: Option<Behavior<AIAction>>,
+    #[serde(default)]
+    pub attack: Option<Attacker>,
+}
+
+impl Prefab {
+    /// Returns this prefab with every component set in `overrides` replaced; markers are added.
+    pub fn merged(&self, overrides: &Prefab) -> Prefab {
+        let mut markers = self.markers.clone();
+        markers.extend(overrides.markers.iter().copied());
+
+        Prefab {
+            markers,
+            health: overrides.health.or(self.health),
+            color: overrides.color.or(self.color),
+            sprite: overrides.sprite.clone().or_else(|| self.sprite.clone()),
+            pursue: overrides.pursue.clone().or_else(|| self.pursue.clone()),
+            behavior: overrides.behavior.clone().or_else(|| self.behavior.clone()),
+            attack: overrides.attack.clone().or_else(|| self.attack.clone()),
+        }
+    }
+
+    /// Adds the components of this prefab, placed at `coords`, to `builder`.
+    pub fn build<B: Builder + MarkedBuilder>(&self, builder: B, coords: (u64, u64), grid_dimensions: &GridDimensions) -> Entity {
+        let (x, y) = coords;
+        let (world_x, world_y) = grid_dimensions.tile_to_world(coords);
+        let mut builder = builder
+            .marked::<SaveMarkers>()
+            .with(Position { x: world_x, y: world_y })
+            .with(Velocity { x: 0.0, y: 0.0 })
+            .with(GridCoords { x, y });
+
+        for marker in &self.markers {
+            builder = match marker {
+                Marker::Player => builder.with(PlayerMarker),
+                Marker::AI => builder.with(AIMarker),
+                Marker::Blob => builder.with(BlobMarker),
+            };
+        }
+        if let Some(health) = self.health {
+            builder = builder.with(Health(health));
+        }
+        if let Some([r, g, b, a]) = self.color {
+            builder = builder.with(Color(Vector4::new(r, g, b, a)));
+        }
+        if let Some(sprite) = &self.sprite {
+            builder = builder.with(PlayerSprite {
+                texture: sprite.texture.clone(),
+                current_frame: sprite.current_frame.clone(),
+                anchor: sprite.anchor.unwrap_or_else(|| sprite.frame_center()),
+                frames: sprite.frames.clone(),
+                facing: String::new(),
+                clips: sprite.clips.clone(),
+                playback: Playback::default(),
+            });
+        }
+
+        if let Some(pursuer) = &self.pursue {
+            builder = builder.with(pursuer.clone());
+        }
+        if let Some(behavior) = &self.behavior {
+            builder = builder.with(AIBehavior::new(behavior.clone()));
+        }
+        if let Some(attacker) = &self.attack {
+            builder = builder.with(attacker.clone());
+        }
+
+        builder.build()
+    }
+}
+
+/// Named archetypes loaded from `assets/prefabs.ron`.
+#[derive(Deserialize, Clone, Default)]
+pub struct PrefabRegistry {
+    prefabs: HashMap<String, Prefab>,
+}
+
+impl PrefabRegistry {
+    pub fn load(path: &Path, sheets: &mut SpriteSheets) -> Result<Self, Box<dyn Error>> {
+        let contents = fs::read_to_string(path)?;
+        let mut registry: PrefabRegistry = ron::from_str(&contents)?;
+        for (name, prefab) in &mut registry.prefabs {
+            if let Some(sprite) = prefab.sprite.as_mut() {
+                sprite.resolve(sheets)?;
+                sprite.check_frames().map_err(|e| format!("Prefab {}: {}", name, e))?;
+            }
+        }
+        Ok(registry)
+    }
+
+    pub fn get(&self, name: &str) -> Result<&Prefab, Box<dyn Error>> {
+        self.prefabs.get(name).ok_or_else(|| format!("Unknown prefab: {}", name).into())
+    }
+
+    /// Creates an instance of `name` at `coords` directly in `world`, which must hold the
+    /// `TileMap`; see `check_placement`.
+    pub fn instantiate(&self, world: &mut World, name: &str, coords: (u64, u64), overrides: &Prefab,
+                       grid_dimensions: &GridDimensions) -> Result<Entity, Box<dyn Error>> {
+        let prefab = self.get(name)?.merged(overrides);
+        check_placement(world, coords, grid_dimensions)?;
+        Ok(prefab.build(world.create_entity(), coords, grid_dimensions))
+    }
+}
+
+/// Fails unless an entity may be placed at `coords`: on the grid, on walkable terrain and
+/// on a tile no living entity stands on.
+pub fn check_placement(world: &World, coords: (u64, u64), grid_dimensions: &GridDimensions) -> Result<(), Box<dyn Error>> {
+    let (x, y) = coords;
+    if !grid_dimensions.contains(coords) {
+        return Err(format!("({}, {}) is outside the {}x{} grid", x, y, grid_dimensions.grid_columns(), grid_dimensions.grid_rows()).into());
+    }
+    if !world.read_resource::<TileMap>().is_walkable(x, y) {
+        return Err(format!("({}, {}) is not walkable", x, y).into());
+    }
+    let occupied = (&world.read_storage::<GridCoords>(), !&world.read_storage::<Death>()).join()
+        .any(|(c, _)| (c.x, c.y) == coords);
+    if occupied {
+        return Err(format!("({}, {}) is already occupied", x, y).into());
+    }
+    Ok(())
+}
@@ -0,0 +1,113 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use piston_window::{Event, Input, Loop};
+use piston_window::event_id::EventId;
+use serde::{Deserialize, Serialize};
+use crate::input_map::InputMap;
+use crate::movement_sys::MovementSettings;
+use crate::turn_sys::TurnMode;
+
+/// The event a single tick was driven by.
+///
+/// Custom events cannot be serialized, so only their occurrence is kept; they do not
+/// affect any system, but replaying them keeps the tick count identical.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum RecordedEvent {
+    Input(Input),
+    Loop(Loop),
+    Custom,
+}
+
+impl RecordedEvent {
+    pub fn from_event(event: &Event) -> Self {
+        match event {
+            Event::Input(input, _) => RecordedEvent::Input(input.clone()),
+            Event::Loop(l) => RecordedEvent::Loop(*l),
+            Event::Custom(_, _, _) => RecordedEvent::Custom,
+        }
+    }
+
+    pub fn to_event(&self) -> Event {
+        match self {
+            RecordedEvent::Input(input) => Event::Input(input.clone(), None),
+            RecordedEvent::Loop(l) => Event::Loop(*l),
+            RecordedEvent::Custom => Event::Custom(EventId("replay"), Arc::new(()), None),
+        }
+    }
+
+    pub fn is_render(&self) -> bool {
+        matches!(self, RecordedEvent::Loop(Loop::Render(_)))
+    }
+}
+
+/// Everything needed to rerun a session tick for tick.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Recording {
+    pub seed: u64,
+    pub level: String,
+    /// Prefab instances spawned on top of the level before the first tick.
+    #[serde(default)]
+    pub spawns: Vec<(String, (u64, u64))>,
+    #[serde(default)]
+    pub turn_mode: TurnMode,
+    /// Multiplier of the game clock the session ran at.
+    #[serde(default = "Recording::default_time_scale")]
+    pub time_scale: f64,
+    /// How entities glided, which decides when the player may move again.
+    #[serde(default)]
+    pub movement: MovementSettings,
+    /// Bindings the buttons in `events` were pressed under; recordings made before
+    /// bindings were kept replay with the current `assets/input.ron`.
+    #[serde(default)]
+    pub input_map: Option<InputMap>,
+    pub events: Vec<RecordedEvent>,
+}
+
+impl Recording {
+    fn default_time_scale() -> f64 {
+        1.0
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let contents = fs::read_to_string(path)?;
+        Ok(ron::from_str(&contents)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        fs::write(path, ron::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+/// Collects the events of every tick and writes them out when dropped, which is when
+/// the session ends or panics, so the run leading up to a crash can be replayed.
+pub struct InputRecorder {
+    path: PathBuf,
+    recording: Recording,
+}
+
+impl InputRecorder {
+    /// Records into `recording`, which holds the session's settings and no events yet.
+    pub fn new(path: PathBuf, recording: Recording) -> Self {
+        Self {
+            path,
+            recording,
+        }
+    }
+
+    pub fn record(&mut self, event: &Event) {
+        self.recording.events.push(RecordedEvent::from_event(event));
+    }
+
+}
+
+impl Drop for InputRecorder {
+    fn drop(&mut self) {
+        match self.recording.save(&self.path) {
+            Ok(()) => println!("Recorded {} ticks to {}", self.recording.events.len(), self.path.display()),
+            Err(e) => eprintln!("Recording could not be written to {}: {}", self.path.display(), e),
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use specs::prelude::*;
+use crate::game_clock::GameClock;
+use crate::game_info::GameInfo;
+use crate::{Death, Health, PlayerMarker};
+
+/// How a game ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Outcome {
+    Victory,
+    Defeat,
+}
+
+/// Running totals of the current game and, once it is over, how it ended.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct GameStats {
+    pub damage_dealt: u32,
+    pub damage_taken: u32,
+    pub outcome: Option<Outcome>,
+    /// Game time the outcome was decided at.
+    pub elapsed: Duration,
+}
+
+/// Decides the outcome: defeat when the player has no health left, victory when every
+/// blob has been killed.
+pub struct RulesSys;
+
+impl<'a> System<'a> for RulesSys {
+    type SystemData = (
+        ReadStorage<'a, PlayerMarker>,
+        ReadStorage<'a, Health>,
+        ReadStorage<'a, Death>,
+        Read<'a, GameInfo>,
+        Read<'a, GameClock>,
+        Write<'a, GameStats>,
+    );
+
+    fn run(&mut self, (players, health, death, game_info, clock, mut stats): Self::SystemData) {
+        if stats.outcome.is_some() {
+            return;
+        }
+
+        let player_dead = (&players, health.maybe(), death.maybe()).join()
+            .any(|(_, h, d)| d.is_some() || h.is_some_and(|h| h.0 == 0));
+        let blobs_dead = !game_info.blobs_health.is_empty() && game_info.blobs_health.iter().all(|h| *h == 0);
+
+        let outcome = if player_dead {
+            Outcome::Defeat
+        } else if blobs_dead {
+            Outcome::Victory
+        } else {
+            return;
+        };
+        stats.outcome = Some(outcome);
+        stats.elapsed = clock.now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game_info::GameInfoSys;
+    use crate::BlobMarker;
+    use super::*;
+
+    fn world(blobs: &[u8]) -> (World, Entity) {
+        let mut world = World::new();
+        world.register::<PlayerMarker>();
+        world.register::<BlobMarker>();
+        world.register::<Health>();
+        world.register::<Death>();
+        world.insert(GameInfo::default());
+        world.insert(GameClock::default());
+        world.insert(GameStats::default());
+        let player = world.create_entity().with(PlayerMarker).with(Health(10)).build();
+        for h in blobs {
+            world.create_entity().with(BlobMarker).with(Health(*h)).build();
+        }
+        (world, player)
+    }
+
+    fn outcome(world: &mut World) -> Option<Outcome> {
+        GameInfoSys.run_now(world);
+        RulesSys.run_now(world);
+        world.read_resource::<GameStats>().outcome
+    }
+
+    #[test]
+    fn victory_once_every_blob_has_no_health() {
+        let (mut world, _) = world(&[0, 3]);
+        assert_eq!(outcome(&mut world), None);
+
+        let mut health = world.write_storage::<Health>();
+        for (_, h) in (&world.read_storage::<BlobMarker>(), &mut health).join() {
+            h.0 = 0;
+        }
+        drop(health);
+        assert_eq!(outcome(&mut world), Some(Outcome::Victory));
+    }
+
+    #[test]
+    fn defeat_when_the_player_dies() {
+        let (mut world, player) = world(&[3]);
+        assert_eq!(outcome(&mut world), None);
+
+        world.write_storage::<Death>().insert(player, Death).unwrap();
+        assert_eq!(outcome(&mut world), Some(Outcome::Defeat));
+    }
+
+    #[test]
+    fn the_first_outcome_is_kept() {
+        let (mut world, player) = world(&[0]);
+        assert_eq!(outcome(&mut world), Some(Outcome::Victory));
+
+        world.write_storage::<Death>().insert(player, Death).unwrap();
+        assert_eq!(outcome(&mut world), Some(Outcome::Victory));
+    }
+}
@@ -0,0 +1,210 @@
+use std::convert::Infallible;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use ron::ser::PrettyConfig;
+use serde::de::{DeserializeSeed, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use specs::prelude::*;
+use specs::saveload::{DeserializeComponents, MarkerAllocator, SerializeComponents, SimpleMarker, SimpleMarkerAllocator};
+use crate::behavior_sys::AIBehavior;
+use crate::game_clock::GameClock;
+use crate::game_info::GameInfo;
+use crate::game_rng::GameRng;
+use crate::rules_sys::GameStats;
+use crate::{ActionLock, AIMarker, Attacker, BlobMarker, Color, Death, Glide, GridCoords, Health, PlayerMarker, PlayerSprite, Position, Pursuer, Velocity};
+
+/// Marks every entity that is written to and restored from save files.
+pub struct SaveMarker;
+
+pub type SaveMarkers = SimpleMarker<SaveMarker>;
+pub type SaveMarkerAllocator = SimpleMarkerAllocator<SaveMarker>;
+
+/// Where the quick-save key writes to and the quick-load key reads from.
+pub const QUICKSAVE_PATH: &str = "saves/quicksave.ron";
+
+/// A save or load asked for by the player, carried out by the simulation after the tick.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaveAction {
+    QuickSave,
+    QuickLoad,
+}
+
+#[derive(Default)]
+pub struct PendingSaveAction(pub Option<SaveAction>);
+
+/// Resources stored next to the entities.
+#[derive(Serialize, Deserialize, Debug)]
+struct SavedState {
+    level: String,
+    seed: u64,
+    #[serde(default)]
+    rng_draws: u64,
+    clock: Duration,
+    game_info: GameInfo,
+    #[serde(default)]
+    stats: GameStats,
+}
+
+type SavedStorages<'a> = (
+    WriteStorage<'a, Position>,
+    WriteStorage<'a, Velocity>,
+    WriteStorage<'a, GridCoords>,
+    WriteStorage<'a, Health>,
+    WriteStorage<'a, Death>,
+    WriteStorage<'a, ActionLock>,
+    WriteStorage<'a, PlayerSprite>,
+    WriteStorage<'a, Color>,
+    WriteStorage<'a, PlayerMarker>,
+    WriteStorage<'a, AIMarker>,
+    WriteStorage<'a, BlobMarker>,
+    WriteStorage<'a, Pursuer>,
+    WriteStorage<'a, AIBehavior>,
+    WriteStorage<'a, Attacker>,
+    WriteStorage<'a, Glide>,
+);
+
+struct SavedEntities<'a, 'b> {
+    entities: &'b Entities<'a>,
+    markers: &'b ReadStorage<'a, SaveMarkers>,
+    storages: &'b SavedStorages<'a>,
+}
+
+impl Serialize for SavedEntities<'_, '_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (position, velocity, coords, health, death, lock, sprite, color, player, ai, blob, pursuer, behavior, attacker, glide) = self.storages;
+        SerializeComponents::<Infallible, SaveMarkers>::serialize(
+            &(position, velocity, coords, health, death, lock, sprite, color, player, ai, blob, pursuer, behavior, attacker, glide),
+            self.entities,
+            self.markers,
+            serializer,
+        )
+    }
+}
+
+struct EntitiesSeed<'a, 'b> {
+    entities: &'b Entities<'a>,
+    markers: &'b mut WriteStorage<'a, SaveMarkers>,
+    allocator: &'b mut SaveMarkerAllocator,
+    storages: &'b mut SavedStorages<'a>,
+}
+
+impl<'de> DeserializeSeed<'de> for EntitiesSeed<'_, '_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        DeserializeComponents::<Infallible, SaveMarkers>::deserialize(
+            self.storages,
+            self.entities,
+            self.markers,
+            self.allocator,
+            deserializer,
+        )
+    }
+}
+
+/// Reads the `(state, entities)` pair a save file consists of.
+struct SaveVisitor<'a, 'b> {
+    entities: EntitiesSeed<'a, 'b>,
+}
+
+impl<'de> Visitor<'de> for SaveVisitor<'_, '_> {
+    type Value = SavedState;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a saved state followed by the saved entities")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<SavedState, A::Error> {
+        use serde::de::Error;
+
+        let state: SavedState = seq.next_element()?
+            .ok_or_else(|| A::Error::invalid_length(0, &self))?;
+        seq.next_element_seed(self.entities)?
+            .ok_or_else(|| A::Error::invalid_length(1, &"a saved state followed by the saved entities"))?;
+        Ok(state)
+    }
+}
+
+/// Writes every marked entity and the resources they depend on to `path`.
+pub fn save_world(world: &World, level: &str, path: &Path) -> Result<(), Box<dyn Error>> {
+    let state = SavedState {
+        level: level.to_string(),
+        seed: world.read_resource::<GameRng>().seed(),
+        rng_draws: world.read_resource::<GameRng>().draws(),
+        clock: world.read_resource::<GameClock>().now(),
+        game_info: (*world.read_resource::<GameInfo>()).clone(),
+        stats: (*world.read_resource::<GameStats>()).clone(),
+    };
+
+    let contents = {
+        let (entities, markers, storages) = world.system_data::<(Entities, ReadStorage<SaveMarkers>, SavedStorages)>();
+        let saved = SavedEntities {
+            entities: &entities,
+            markers: &markers,
+            storages: &storages,
+        };
+        ron::ser::to_string_pretty(&(&state, &saved), PrettyConfig::default())?
+    };
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, contents)?;
+    Ok(())
+}
+
+/// Replaces every marked entity in `world` with the ones stored at `path` and restores
+/// the saved resources. The save must have been made on `level`; a save that cannot be
+/// read or comes from another level leaves the world untouched.
+pub fn load_world(world: &mut World, path: &Path, level: &str) -> Result<(), Box<dyn Error>> {
+    let contents = fs::read_to_string(path)?;
+
+    // Reading into an empty world first means a broken save fails before anything is deleted.
+    let state = read_save(&mut World::new(), &contents)?;
+    if state.level != level {
+        return Err(format!("save was made on level {} while playing {}", state.level, level).into());
+    }
+
+    {
+        let entities = world.entities();
+        let markers = world.read_storage::<SaveMarkers>();
+        for (entity, _) in (&entities, &markers).join() {
+            entities.delete(entity)?;
+        }
+    }
+    world.maintain();
+    world.exec(|(entities, markers, mut allocator): (Entities, ReadStorage<SaveMarkers>, Write<SaveMarkerAllocator>)| {
+        allocator.maintain(&entities, &markers);
+    });
+
+    let state = read_save(world, &contents)?;
+
+    // The time scale and pause state of the running session are kept.
+    world.write_resource::<GameClock>().set_now(state.clock);
+    world.insert::<GameInfo>(state.game_info);
+    world.insert::<GameStats>(state.stats);
+    world.insert::<GameRng>(GameRng::from_state(state.seed, state.rng_draws));
+    world.maintain();
+
+    Ok(())
+}
+
+/// Creates the entities stored in `contents` in `world`, registering whatever it lacks.
+fn read_save(world: &mut World, contents: &str) -> Result<SavedState, Box<dyn Error>> {
+    world.exec(|(entities, mut markers, mut allocator, mut storages): (Entities, WriteStorage<SaveMarkers>, Write<SaveMarkerAllocator>, SavedStorages)| {
+        let mut deserializer = ron::Deserializer::from_str(contents)?;
+        let state = deserializer.deserialize_tuple(2, SaveVisitor {
+            entities: EntitiesSeed {
+                entities: &entities,
+                markers: &mut markers,
+                allocator: &mut allocator,
+                storages: &mut storages,
+            },
+        })?;
+        deserializer.end()?;
+        Ok::<_, Box<dyn Error>>(state)
+    })
+}
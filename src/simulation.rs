@@ -0,0 +1,414 @@
+use std::error::Error;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use piston_window::{Event, Loop, UpdateArgs};
+use specs::prelude::*;
+use specs::shred::FetchMut;
+use crate::ai_attack_sys::AIAttackSys;
+use crate::ai_sys::AISys;
+use crate::animation_sys::{AnimationEvents, AnimationSys};
+use crate::behavior_sys::{AIBehavior, BehaviorSys};
+use crate::blob_interaction::BlobInteractionSys;
+use crate::camera::{Camera, CameraSys};
+use crate::cleanup_sys::CleanupSys;
+use crate::collisions_sys::PositionsMap;
+use crate::game_clock::{GameClock, GameClockSys};
+use crate::game_rng::GameRng;
+use crate::game_state::{GameState, GameStateSys, RestartRequested};
+use crate::game_info::{GameInfo, GameInfoSys};
+use crate::grid_changes::GridChangesSys;
+use crate::health_sys::HealthSys;
+use crate::input_map::{Cursor, GamepadState, InputMap, InputMapSys, PlayerActions};
+use crate::input_sys::{ActionFired, InputSys};
+use crate::level::Level;
+use crate::movement_sys::{MovementSettings, MovementSys};
+use crate::pathfinding::PathCache;
+use crate::pointer_sys::{PointerSys, Selection};
+use crate::prefabs::{Prefab, PrefabRegistry};
+use crate::rules_sys::{GameStats, RulesSys};
+use crate::tile_map::TileMap;
+use crate::turn_sys::{TurnMode, TurnSys, Turns};
+use crate::save_game::{load_world, save_world, PendingSaveAction, SaveAction, SaveMarkerAllocator, SaveMarkers, QUICKSAVE_PATH};
+use crate::sprite_movement_sys::SpriteMovementSys;
+use crate::update_position_sys::UpdatePos;
+use crate::{ActionLock, AIMarker, Attacker, BlobMarker, Color, Damage, Death, Destination, Glide, GridCoords, GridDimensions, Health, InputEvent,
+            NewGridCoords, PlayerMarker, PlayerSprite, Position, Pursuer, Velocity};
+
+/// Level loaded when none is given on the command line.
+pub const DEFAULT_LEVEL: &str = "level1";
+
+/// Owns the game `World` and the systems that drive it, independently of any window.
+///
+/// Every call to `step` is one tick: the event is published as the `InputEvent` resource,
+/// the state systems run, the gameplay systems run too while the game is being played,
+/// and the world is maintained. Button presses and controller input reach the systems
+/// as `PlayerActions` through the `InputMap`.
+pub struct Simulation {
+    world: World,
+    state_dispatcher: Dispatcher<'static, 'static>,
+    dispatcher: Dispatcher<'static, 'static>,
+    level: Level,
+    spawns: Vec<(String, (u64, u64))>,
+    ticks: u64,
+    resets: u64,
+}
+
+impl Simulation {
+    /// Creates the simulation of `level`, waiting in the menu.
+    pub fn new(level: &Level, prefabs: PrefabRegistry, seed: u64) -> Self {
+        let state_dispatcher = DispatcherBuilder::new()
+            .with(InputMapSys, "input_map", &[])
+            .with(GameStateSys, "game_state", &["input_map"])
+            .with(GridChangesSys, "grid_changes", &[])
+            .with(CameraSys, "camera", &["input_map", "grid_changes"])
+            .build();
+
+        let dispatcher = DispatcherBuilder::new()
+            .with(GameClockSys, "clock", &[])
+            .with(InputSys, "input", &["clock"])
+            .with(PointerSys, "pointer", &["input"])
+            .with(TurnSys, "turns", &["input", "pointer"])
+            .with(AIAttackSys, "ai_attack", &["turns"])
+            .with(AISys, "ai_sys", &["turns", "ai_attack"])
+            .with(BehaviorSys, "behavior", &["turns"])
+            .with(SpriteMovementSys, "sprite_updates", &["input", "pointer", "ai_sys", "behavior"])
+            .with(UpdatePos, "update_pos", &["input", "pointer", "turns"])
+            .with(MovementSys, "movement", &["update_pos"])
+            .with(BlobInteractionSys, "blob_interaction", &["update_pos"])
+            .with(HealthSys, "health", &["blob_interaction"])
+            // Clears the `InputEvent`, so it has to wait for every system reading it.
+            .with(CleanupSys, "cleanup", &["input", "behavior", "movement"])
+            .with(AnimationSys, "animation", &["sprite_updates", "movement", "health"])
+            .with(GameInfoSys, "game_info", &["cleanup"])
+            .with(RulesSys, "rules", &["health", "game_info"])
+            .build();
+
+        Self {
+            world: Self::create_world(level, prefabs, seed),
+            state_dispatcher,
+            dispatcher,
+            level: level.clone(),
+            spawns: Vec::new(),
+            ticks: 0,
+            resets: 0,
+        }
+    }
+
+    fn create_world(level: &Level, prefabs: PrefabRegistry, seed: u64) -> World {
+        let mut world = World::new();
+        world.register::<Position>();
+        world.register::<Velocity>();
+        world.register::<GridCoords>();
+        world.register::<PlayerMarker>();
+        world.register::<AIMarker>();
+        world.register::<BlobMarker>();
+        world.register::<NewGridCoords>();
+        world.register::<PlayerSprite>();
+        world.register::<ActionFired>();
+        world.register::<Color>();
+        world.register::<Health>();
+        world.register::<Damage>();
+        world.register::<Death>();
+        world.register::<ActionLock>();
+        world.register::<Pursuer>();
+        world.register::<AIBehavior>();
+        world.register::<Attacker>();
+        world.register::<Glide>();
+        world.register::<Destination>();
+        world.register::<SaveMarkers>();
+        world.insert::<InputEvent>(InputEvent(None));
+        world.insert::<InputMap>(InputMap::default());
+        world.insert::<PlayerActions>(PlayerActions::default());
+        world.insert::<GamepadState>(GamepadState::default());
+        world.insert::<Cursor>(Cursor::default());
+        world.insert::<Selection>(Selection::default());
+        world.insert::<SaveMarkerAllocator>(SaveMarkerAllocator::new());
+        world.insert::<PendingSaveAction>(PendingSaveAction::default());
+        world.insert::<GameState>(GameState::default());
+        world.insert::<Turns>(Turns::default());
+        world.insert::<MovementSettings>(MovementSettings::default());
+        world.insert::<AnimationEvents>(AnimationEvents::default());
+        world.insert::<RestartRequested>(RestartRequested::default());
+
+        let grid_dimensions = level.grid_dimensions();
+        world.insert::<Camera>(Camera::new(grid_dimensions.board_size()));
+        world.insert::<TileMap>(level.tile_map().expect("Level tiles could not be read"));
+        level.populate(&mut world, &grid_dimensions, &prefabs)
+            .expect("Level entities could not be created");
+        world.insert::<PrefabRegistry>(prefabs);
+
+        world.insert::<Arc<Mutex<GridDimensions>>>(Arc::new(Mutex::new(grid_dimensions)));
+        world.insert::<GameInfo>(GameInfo::default());
+        world.insert::<GameStats>(GameStats::default());
+        world.insert::<PositionsMap>(PositionsMap::default());
+        world.insert::<PathCache>(PathCache::default());
+        world.insert::<GameClock>(GameClock::default());
+        world.insert::<GameRng>(GameRng::from_seed(seed));
+
+        world
+    }
+
+    /// Creates an instance of the prefab `name` at `coords`, with `overrides` replacing its
+    /// components. Fails when the tile is off the grid, not walkable or occupied.
+    pub fn spawn(&mut self, name: &str, coords: (u64, u64), overrides: &Prefab) -> Result<Entity, Box<dyn Error>> {
+        let grid_dimensions = self.grid_dimensions();
+        let grid_dimensions = grid_dimensions.lock().unwrap();
+        let prefabs = self.world.remove::<PrefabRegistry>().unwrap_or_default();
+        let spawned = prefabs.instantiate(&mut self.world, name, coords, overrides, &grid_dimensions);
+        self.world.insert::<PrefabRegistry>(prefabs);
+        if spawned.is_ok() {
+            self.spawns.push((name.to_string(), coords));
+        }
+        spawned
+    }
+
+    /// Runs a single tick with `event` as the current input.
+    pub fn step(&mut self, event: Event) {
+        self.world.insert::<InputEvent>(InputEvent(Some(event)));
+
+        self.state_dispatcher.dispatch(&self.world);
+        if self.state().is_playing() {
+            self.dispatcher.dispatch(&self.world);
+        }
+        self.world.maintain();
+        self.ticks += 1;
+
+        if std::mem::take(&mut self.world.write_resource::<RestartRequested>().0) {
+            self.restart();
+        }
+
+        let pending = self.world.write_resource::<PendingSaveAction>().0.take();
+        let result = match pending {
+            Some(SaveAction::QuickSave) => self.save(Path::new(QUICKSAVE_PATH)),
+            Some(SaveAction::QuickLoad) => self.load(Path::new(QUICKSAVE_PATH)),
+            None => Ok(()),
+        };
+        if let Err(e) = result {
+            eprintln!("{:?} failed: {}", pending, e);
+        }
+    }
+
+    pub fn set_turn_mode(&mut self, mode: TurnMode) {
+        self.world.insert::<Turns>(Turns::new(mode));
+    }
+
+    pub fn set_input_map(&mut self, input_map: InputMap) {
+        self.world.insert::<InputMap>(input_map);
+    }
+
+    pub fn set_movement(&mut self, settings: MovementSettings) {
+        self.world.insert::<MovementSettings>(settings);
+    }
+
+    /// Leaves the menu and starts playing.
+    pub fn start(&mut self) {
+        *self.world.write_resource::<GameState>() = GameState::Playing;
+    }
+
+    /// Starts the level over with the same seed and spawns, keeping the clock's settings
+    /// and the camera and grid dimensions shared with the window.
+    pub fn restart(&mut self) {
+        let prefabs = (*self.world.read_resource::<PrefabRegistry>()).clone();
+        let grid_dimensions = self.grid_dimensions();
+        let turn_mode = self.world.read_resource::<Turns>().mode();
+        let movement = *self.world.read_resource::<MovementSettings>();
+        let input_map = self.world.remove::<InputMap>().unwrap_or_default();
+        let camera = self.world.remove::<Camera>().unwrap_or_default();
+        let mut clock = self.world.remove::<GameClock>().unwrap_or_default();
+        clock.set_now(Duration::ZERO);
+        clock.resume();
+
+        self.world = Self::create_world(&self.level, prefabs, self.seed());
+        self.world.insert::<GameClock>(clock);
+        self.world.insert::<Arc<Mutex<GridDimensions>>>(grid_dimensions);
+        self.world.insert::<Turns>(Turns::new(turn_mode));
+        self.world.insert::<MovementSettings>(movement);
+        self.world.insert::<InputMap>(input_map);
+        self.world.insert::<Camera>(camera);
+        for (name, coords) in std::mem::take(&mut self.spawns) {
+            if let Err(e) = self.spawn(&name, coords, &Prefab::default()) {
+                eprintln!("Could not spawn {} again: {}", name, e);
+            }
+        }
+        self.resets += 1;
+        self.start();
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        save_world(&self.world, &self.level.id, path)?;
+        println!("Saved game to {}", path.display());
+        Ok(())
+    }
+
+    /// Restores a game saved with `save`; the save must come from the level being played.
+    pub fn load(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        load_world(&mut self.world, path, &self.level.id)?;
+        self.resets += 1;
+        println!("Loaded game from {}", path.display());
+        Ok(())
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    pub fn grid_dimensions(&self) -> Arc<Mutex<GridDimensions>> {
+        Arc::clone(&self.world.read_resource::<Arc<Mutex<GridDimensions>>>())
+    }
+
+    pub fn clock_mut(&mut self) -> FetchMut<'_, GameClock> {
+        self.world.write_resource::<GameClock>()
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.world.read_resource::<GameRng>().seed()
+    }
+
+    pub fn level(&self) -> &str {
+        &self.level.id
+    }
+
+    pub fn state(&self) -> GameState {
+        *self.world.read_resource::<GameState>()
+    }
+
+    /// Counts restarts and loads, after which no entity from before is still in the world.
+    pub fn resets(&self) -> u64 {
+        self.resets
+    }
+
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+}
+
+/// Builds an update event advancing the loop by `dt` seconds.
+pub fn update_event(dt: f64) -> Event {
+    Event::Loop(Loop::Update(UpdateArgs { dt }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use piston_window::{Button, ButtonArgs, ButtonState, Input, Key};
+    use crate::sprite_sheet::SpriteSheets;
+    use super::*;
+
+    fn assets() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets")
+    }
+
+    /// A game of level 1 with a chaser added, already started.
+    fn simulation(seed: u64) -> Simulation {
+        let mut sheets = SpriteSheets::new(assets().join("sprites"));
+        let level = Level::load(&assets().join("levels").join("level1.ron"), &mut sheets).unwrap();
+        let prefabs = PrefabRegistry::load(&assets().join("prefabs.ron"), &mut sheets).unwrap();
+        let mut simulation = Simulation::new(&level, prefabs, seed);
+        simulation.spawn("chaser", (11, 7), &Prefab::default()).unwrap();
+        simulation.start();
+        simulation
+    }
+
+    fn ai_coords(simulation: &Simulation) -> Vec<(u64, u64)> {
+        let world = simulation.world();
+        (&world.read_storage::<GridCoords>(), &world.read_storage::<AIMarker>()).join()
+            .map(|(c, _)| (c.x, c.y))
+            .collect()
+    }
+
+    /// AI positions once every simulated second, for `seconds` seconds.
+    fn trajectory(seed: u64, seconds: u64) -> Vec<Vec<(u64, u64)>> {
+        let mut simulation = simulation(seed);
+        (0..seconds)
+            .map(|_| {
+                for _ in 0..60 {
+                    simulation.step(update_event(1.0 / 60.0));
+                }
+                ai_coords(&simulation)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ai_follows_the_same_trajectory_for_a_seed() {
+        // The wanderer at (4, 4) draws from the seeded RNG; the chaser walks along the
+        // bottom row, below the wall, towards the player at (0, 0).
+        assert_eq!(trajectory(3, 6), vec![
+            vec![(5, 4), (9, 7)],
+            vec![(4, 4), (7, 7)],
+            vec![(5, 4), (5, 7)],
+            vec![(4, 4), (3, 7)],
+            vec![(4, 3), (1, 7)],
+            vec![(4, 3), (0, 6)],
+        ]);
+    }
+
+    #[test]
+    fn runs_with_the_same_seed_are_identical() {
+        assert_eq!(trajectory(42, 10), trajectory(42, 10));
+    }
+
+    fn run(simulation: &mut Simulation, ticks: u64) {
+        for _ in 0..ticks {
+            simulation.step(update_event(1.0 / 60.0));
+        }
+    }
+
+    #[test]
+    fn loading_a_save_continues_the_game_where_it_was_saved() {
+        let path = std::env::temp_dir().join(format!("blobs-save-test-{}.ron", std::process::id()));
+        let mut uninterrupted = simulation(3);
+        let mut reloaded = simulation(3);
+
+        run(&mut uninterrupted, 120);
+        run(&mut reloaded, 120);
+        reloaded.save(&path).unwrap();
+        // Whatever happens after the save is undone by loading it.
+        run(&mut reloaded, 300);
+        reloaded.load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        run(&mut uninterrupted, 600);
+        run(&mut reloaded, 600);
+        // Loaded entities get new ids, so only the set of positions is compared.
+        let sorted = |simulation: &Simulation| {
+            let mut coords = ai_coords(simulation);
+            coords.sort();
+            coords
+        };
+        assert_eq!(sorted(&reloaded), sorted(&uninterrupted));
+        assert_eq!(reloaded.world().read_resource::<GameRng>().draws(),
+                   uninterrupted.world().read_resource::<GameRng>().draws());
+    }
+
+    fn press(simulation: &mut Simulation, key: Key) {
+        let input = Input::Button(ButtonArgs { state: ButtonState::Press, button: Button::Keyboard(key), scancode: None });
+        simulation.step(Event::Input(input, None));
+    }
+
+    fn player_coords(simulation: &Simulation) -> (u64, u64) {
+        let world = simulation.world();
+        (&world.read_storage::<GridCoords>(), &world.read_storage::<PlayerMarker>()).join()
+            .map(|(c, _)| (c.x, c.y))
+            .next()
+            .unwrap()
+    }
+
+    #[test]
+    fn player_input_waits_for_the_glide_to_finish() {
+        let mut simulation = simulation(3);
+        press(&mut simulation, Key::Right);
+        run(&mut simulation, 3);
+        assert!(simulation.world().read_storage::<Glide>().join().next().is_some());
+
+        // Pressed mid-glide, this move is dropped rather than queued.
+        press(&mut simulation, Key::Right);
+        run(&mut simulation, 30);
+        assert_eq!(player_coords(&simulation), (1, 0));
+
+        press(&mut simulation, Key::Right);
+        run(&mut simulation, 30);
+        assert_eq!(player_coords(&simulation), (2, 0));
+    }
+}
@@ -10,27 +10,27 @@ impl<'a> System<'a> for SpriteMovementSys {
                        ReadStorage<'a, GridCoords>,
                        WriteStorage<'a, PlayerSprite>);
 
-    fn run(&mut self, (mut new_coords, mut coords, mut sprite): Self::SystemData) {
+    fn run(&mut self, (new_coords, coords, mut sprite): Self::SystemData) {
         use specs::Join;
 
         for (new_coord, coord, sprite) in (&new_coords, &coords, &mut sprite).join() {
             match coord.x.cmp(&new_coord.x) {
                 Ordering::Less => {
-                    sprite.update_frame("right")
+                    sprite.face("right")
                 }
                 Ordering::Equal => {}
                 Ordering::Greater => {
-                    sprite.update_frame("left")
+                    sprite.face("left")
                 }
             }
 
             match coord.y.cmp(&new_coord.y) {
                 Ordering::Less => {
-                    sprite.update_frame("vertical")
+                    sprite.face("vertical")
                 }
                 Ordering::Equal => {}
                 Ordering::Greater => {
-                    sprite.update_frame("vertical")
+                    sprite.face("vertical")
                 }
             }
         }
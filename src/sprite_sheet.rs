@@ -0,0 +1,303 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::de::{MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+use crate::animation_sys::{AnimationClip, ClipFrame};
+
+/// Frames shown for this long when the sheet gives no duration, as TexturePacker's don't.
+const DEFAULT_FRAME_MS: f64 = 100.0;
+
+/// Named frames, anchor and animations of one texture, read from a JSON sprite sheet.
+///
+/// Both layouts exported by Aseprite and TexturePacker are understood: `frames` as an
+/// object keyed by frame name or as an array of frames with a `filename`. Animations are
+/// Aseprite's `meta.frameTags`, which index into the frames in file order.
+#[derive(Debug, Clone)]
+pub struct SpriteSheet {
+    pub image: String,
+    pub frames: HashMap<String, [f64; 4]>,
+    /// Name of the first frame in the file, shown until something else is picked.
+    pub first_frame: String,
+    pub anchor: (f64, f64),
+    pub clips: HashMap<String, AnimationClip>,
+}
+
+#[derive(Deserialize)]
+struct SheetFile {
+    #[serde(deserialize_with = "frames_in_order")]
+    frames: Vec<SheetFrame>,
+    meta: SheetMeta,
+}
+
+#[derive(Deserialize)]
+struct SheetFrame {
+    #[serde(default)]
+    filename: String,
+    frame: Rect,
+    /// Milliseconds, as Aseprite writes it.
+    #[serde(default)]
+    duration: Option<f64>,
+    /// Fraction of the source size, as TexturePacker writes it.
+    #[serde(default)]
+    pivot: Option<Point>,
+    #[serde(default, rename = "sourceSize")]
+    source_size: Option<Size>,
+    /// Packed frames turned or cropped would need drawing differently, which isn't done.
+    #[serde(default)]
+    rotated: bool,
+    #[serde(default)]
+    trimmed: bool,
+}
+
+#[derive(Deserialize)]
+struct Rect {
+    x: f64,
+    y: f64,
+    w: f64,
+    h: f64,
+}
+
+#[derive(Deserialize)]
+struct Point {
+    x: f64,
+    y: f64,
+}
+
+#[derive(Deserialize)]
+struct Size {
+    w: f64,
+    h: f64,
+}
+
+#[derive(Deserialize)]
+struct SheetMeta {
+    image: String,
+    #[serde(default, rename = "frameTags")]
+    frame_tags: Vec<FrameTag>,
+}
+
+#[derive(Deserialize)]
+struct FrameTag {
+    name: String,
+    from: usize,
+    to: usize,
+    #[serde(default)]
+    direction: Option<String>,
+    /// Aseprite only writes this for tags that play a limited number of times.
+    #[serde(default)]
+    repeat: Option<String>,
+}
+
+impl SpriteSheet {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let contents = fs::read_to_string(path)?;
+        Self::parse(&contents).map_err(|e| format!("Sprite sheet {} is invalid: {}", path.display(), e).into())
+    }
+
+    pub fn parse(json: &str) -> Result<Self, Box<dyn Error>> {
+        let file: SheetFile = serde_json::from_str(json)?;
+        let first = file.frames.first().ok_or("sheet has no frames")?;
+        if let Some(frame) = file.frames.iter().find(|f| f.rotated || f.trimmed) {
+            return Err(format!("frame {} is rotated or trimmed, which is not supported", frame.filename).into());
+        }
+
+        let anchor = match (&first.pivot, &first.source_size) {
+            (Some(pivot), Some(size)) => (pivot.x * size.w, pivot.y * size.h),
+            (Some(pivot), None) => (pivot.x * first.frame.w, pivot.y * first.frame.h),
+            (None, _) => (first.frame.w / 2.0, first.frame.h / 2.0),
+        };
+
+        let mut clips = HashMap::new();
+        for tag in &file.meta.frame_tags {
+            if tag.from > tag.to || tag.to >= file.frames.len() {
+                return Err(format!("tag {} covers frames outside the sheet", tag.name).into());
+            }
+            let forward = (tag.from..=tag.to).collect::<Vec<_>>();
+            let order = match tag.direction.as_deref().unwrap_or("forward") {
+                "forward" => forward,
+                "reverse" => forward.into_iter().rev().collect(),
+                "pingpong" => {
+                    let back = forward.iter().rev().skip(1).take(forward.len().saturating_sub(2)).copied().collect::<Vec<_>>();
+                    forward.into_iter().chain(back).collect()
+                }
+                other => return Err(format!("tag {} has unknown direction {}", tag.name, other).into()),
+            };
+
+            clips.insert(tag.name.clone(), AnimationClip {
+                frames: order.into_iter()
+                    .map(|i| ClipFrame {
+                        frame: file.frames[i].filename.clone(),
+                        duration: file.frames[i].duration.unwrap_or(DEFAULT_FRAME_MS) / 1000.0,
+                    })
+                    .collect(),
+                looping: tag.repeat.is_none(),
+            });
+        }
+
+        Ok(Self {
+            image: file.meta.image,
+            first_frame: first.filename.clone(),
+            frames: file.frames.iter()
+                .map(|f| (f.filename.clone(), [f.frame.x, f.frame.y, f.frame.w, f.frame.h]))
+                .collect(),
+            anchor,
+            clips,
+        })
+    }
+}
+
+/// Reads `frames` either as an object, keeping the file's order, or as an array.
+fn frames_in_order<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<SheetFrame>, D::Error> {
+    struct FramesVisitor;
+
+    impl<'de> Visitor<'de> for FramesVisitor {
+        type Value = Vec<SheetFrame>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("an object or array of frames")
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut frames = Vec::new();
+            while let Some(frame) = seq.next_element()? {
+                frames.push(frame);
+            }
+            Ok(frames)
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+            let mut frames = Vec::new();
+            while let Some((name, mut frame)) = map.next_entry::<String, SheetFrame>()? {
+                frame.filename = name;
+                frames.push(frame);
+            }
+            Ok(frames)
+        }
+    }
+
+    deserializer.deserialize_any(FramesVisitor)
+}
+
+/// Sprite sheets in a folder, each loaded the first time it is asked for by name.
+pub struct SpriteSheets {
+    folder: PathBuf,
+    sheets: HashMap<String, SpriteSheet>,
+}
+
+impl SpriteSheets {
+    pub fn new(folder: PathBuf) -> Self {
+        Self {
+            folder,
+            sheets: HashMap::new(),
+        }
+    }
+
+    pub fn get(&mut self, name: &str) -> Result<&SpriteSheet, Box<dyn Error>> {
+        if !self.sheets.contains_key(name) {
+            let sheet = SpriteSheet::load(&self.folder.join(format!("{}.json", name)))?;
+            self.sheets.insert(name.to_string(), sheet);
+        }
+        Ok(&self.sheets[name])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Aseprite's layout: frames keyed by name, durations and tags.
+    const ASEPRITE: &str = r#"{
+        "frames": {
+            "a": { "frame": { "x": 0, "y": 0, "w": 32, "h": 48 }, "duration": 50 },
+            "b": { "frame": { "x": 32, "y": 0, "w": 32, "h": 48 }, "duration": 150 },
+            "c": { "frame": { "x": 64, "y": 0, "w": 32, "h": 48 } }
+        },
+        "meta": {
+            "image": "sheet.png",
+            "frameTags": [
+                { "name": "walk", "from": 0, "to": 2, "direction": "forward" },
+                { "name": "back", "from": 0, "to": 2, "direction": "reverse" },
+                { "name": "bounce", "from": 0, "to": 2, "direction": "pingpong" },
+                { "name": "hit", "from": 1, "to": 1, "repeat": "1" }
+            ]
+        }
+    }"#;
+
+    /// TexturePacker's array layout, with a pivot and no durations or tags.
+    const TEXTURE_PACKER: &str = r#"{
+        "frames": [
+            { "filename": "idle", "frame": { "x": 0, "y": 0, "w": 40, "h": 40 },
+              "rotated": false, "trimmed": false,
+              "spriteSourceSize": { "x": 0, "y": 0, "w": 40, "h": 40 },
+              "sourceSize": { "w": 40, "h": 40 }, "pivot": { "x": 0.5, "y": 1.0 } },
+            { "filename": "jump", "frame": { "x": 40, "y": 0, "w": 40, "h": 40 },
+              "rotated": false, "trimmed": false,
+              "sourceSize": { "w": 40, "h": 40 }, "pivot": { "x": 0.5, "y": 1.0 } }
+        ],
+        "meta": { "image": "packed.png" }
+    }"#;
+
+    fn clip_frames(sheet: &SpriteSheet, clip: &str) -> Vec<(String, f64)> {
+        sheet.clips[clip].frames.iter().map(|f| (f.frame.clone(), f.duration)).collect()
+    }
+
+    #[test]
+    fn parses_aseprite_object_layout() {
+        let sheet = SpriteSheet::parse(ASEPRITE).unwrap();
+        assert_eq!(sheet.image, "sheet.png");
+        assert_eq!(sheet.first_frame, "a");
+        assert_eq!(sheet.frames["b"], [32.0, 0.0, 32.0, 48.0]);
+        // Without a pivot the anchor is the middle of the first frame.
+        assert_eq!(sheet.anchor, (16.0, 24.0));
+    }
+
+    #[test]
+    fn parses_texture_packer_array_layout() {
+        let sheet = SpriteSheet::parse(TEXTURE_PACKER).unwrap();
+        assert_eq!(sheet.image, "packed.png");
+        assert_eq!(sheet.first_frame, "idle");
+        assert_eq!(sheet.frames.len(), 2);
+        assert_eq!(sheet.frames["jump"], [40.0, 0.0, 40.0, 40.0]);
+        assert_eq!(sheet.anchor, (20.0, 40.0));
+        assert!(sheet.clips.is_empty());
+    }
+
+    #[test]
+    fn clips_follow_tag_directions() {
+        let sheet = SpriteSheet::parse(ASEPRITE).unwrap();
+        let names = |clip| clip_frames(&sheet, clip).into_iter().map(|(name, _)| name).collect::<Vec<_>>();
+        assert_eq!(names("walk"), ["a", "b", "c"]);
+        assert_eq!(names("back"), ["c", "b", "a"]);
+        assert_eq!(names("bounce"), ["a", "b", "c", "b"]);
+        assert_eq!(names("hit"), ["b"]);
+    }
+
+    #[test]
+    fn only_tags_with_a_repeat_count_play_once() {
+        let sheet = SpriteSheet::parse(ASEPRITE).unwrap();
+        assert!(sheet.clips["walk"].looping);
+        assert!(!sheet.clips["hit"].looping);
+    }
+
+    #[test]
+    fn missing_durations_default_to_100_ms() {
+        let sheet = SpriteSheet::parse(ASEPRITE).unwrap();
+        assert_eq!(clip_frames(&sheet, "walk"), [("a".to_string(), 0.05), ("b".to_string(), 0.15), ("c".to_string(), 0.1)]);
+    }
+
+    #[test]
+    fn rejects_rotated_and_trimmed_frames() {
+        assert!(SpriteSheet::parse(&TEXTURE_PACKER.replacen(r#""rotated": false"#, r#""rotated": true"#, 1)).is_err());
+        assert!(SpriteSheet::parse(&TEXTURE_PACKER.replacen(r#""trimmed": false"#, r#""trimmed": true"#, 1)).is_err());
+    }
+
+    #[test]
+    fn rejects_tags_outside_the_sheet_and_unknown_directions() {
+        assert!(SpriteSheet::parse(&ASEPRITE.replace(r#""to": 2, "direction": "forward""#, r#""to": 3, "direction": "forward""#)).is_err());
+        assert!(SpriteSheet::parse(&ASEPRITE.replace("pingpong", "sideways")).is_err());
+        assert!(SpriteSheet::parse(r#"{ "frames": [], "meta": { "image": "x.png" } }"#).is_err());
+    }
+}
@@ -0,0 +1,104 @@
+use std::error::Error;
+use serde::{Deserialize, Serialize};
+
+/// What a single grid tile is made of.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Terrain {
+    #[default]
+    Floor,
+    Grass,
+    Wall,
+    Water,
+}
+
+impl Terrain {
+    /// Character used for this terrain in level layouts.
+    pub fn from_char(c: char) -> Option<Self> {
+        match c {
+            '.' => Some(Terrain::Floor),
+            ',' => Some(Terrain::Grass),
+            '#' => Some(Terrain::Wall),
+            '~' => Some(Terrain::Water),
+            _ => None,
+        }
+    }
+
+    pub fn is_walkable(&self) -> bool {
+        match self {
+            Terrain::Floor | Terrain::Grass => true,
+            Terrain::Wall | Terrain::Water => false,
+        }
+    }
+
+    /// Fill color of the tile; floor is left unpainted.
+    pub fn color(&self) -> Option<[f32; 4]> {
+        match self {
+            Terrain::Floor => None,
+            Terrain::Grass => Some([0.75, 0.9, 0.6, 1.0]),
+            Terrain::Wall => Some([0.35, 0.3, 0.3, 1.0]),
+            Terrain::Water => Some([0.4, 0.6, 0.95, 1.0]),
+        }
+    }
+}
+
+/// Terrain of every tile of the level.
+///
+/// Tiles outside the map are treated as walls.
+#[derive(Debug, Default)]
+pub struct TileMap {
+    columns: u64,
+    rows: u64,
+    tiles: Vec<Terrain>,
+}
+
+impl TileMap {
+    pub fn filled(columns: u64, rows: u64, terrain: Terrain) -> Self {
+        Self {
+            columns,
+            rows,
+            tiles: vec![terrain; (columns * rows) as usize],
+        }
+    }
+
+    /// Builds a map from one string per row, using the characters of `Terrain::from_char`.
+    /// Rows shorter than `columns`, and missing rows, are filled with floor.
+    pub fn from_layout(columns: u64, rows: u64, layout: &[String]) -> Result<Self, Box<dyn Error>> {
+        let mut map = Self::filled(columns, rows, Terrain::Floor);
+        for (y, line) in layout.iter().enumerate().take(rows as usize) {
+            for (x, c) in line.chars().enumerate().take(columns as usize) {
+                let terrain = Terrain::from_char(c)
+                    .ok_or_else(|| format!("Unknown terrain '{}' at ({}, {})", c, x, y))?;
+                map.set(x as u64, y as u64, terrain);
+            }
+        }
+        Ok(map)
+    }
+
+    fn index(&self, x: u64, y: u64) -> Option<usize> {
+        if x < self.columns && y < self.rows {
+            Some((y * self.columns + x) as usize)
+        } else {
+            None
+        }
+    }
+
+    pub fn terrain_at(&self, x: u64, y: u64) -> Terrain {
+        self.index(x, y).map(|i| self.tiles[i]).unwrap_or(Terrain::Wall)
+    }
+
+    pub fn set(&mut self, x: u64, y: u64, terrain: Terrain) {
+        if let Some(i) = self.index(x, y) {
+            self.tiles[i] = terrain;
+        }
+    }
+
+    pub fn is_walkable(&self, x: u64, y: u64) -> bool {
+        self.terrain_at(x, y).is_walkable()
+    }
+
+    /// Every tile with its coordinates, row by row.
+    pub fn tiles(&self) -> impl Iterator<Item = (u64, u64, Terrain)> + '_ {
+        self.tiles.iter().enumerate()
+            .map(move |(i, t)| (i as u64 % self.columns, i as u64 / self.columns, *t))
+    }
+}
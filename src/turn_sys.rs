@@ -0,0 +1,97 @@
+use serde::{Deserialize, Serialize};
+use specs::prelude::*;
+use crate::{ActionLock, AIMarker, Glide, Position, Velocity};
+
+/// How AI entities get to act.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TurnMode {
+    /// AI acts whenever its `ActionLock` has run out.
+    #[default]
+    RealTime,
+    /// AI acts once after every move or attack of the player.
+    TurnBased,
+}
+
+/// Whose turn it is.
+#[derive(Debug, Default)]
+pub struct Turns {
+    mode: TurnMode,
+    player_acted: bool,
+    ai_turn: bool,
+}
+
+impl Turns {
+    pub fn new(mode: TurnMode) -> Self {
+        Self {
+            mode,
+            ..Self::default()
+        }
+    }
+
+    pub fn mode(&self) -> TurnMode {
+        self.mode
+    }
+
+    pub fn is_turn_based(&self) -> bool {
+        self.mode == TurnMode::TurnBased
+    }
+
+    /// Records that the player used up their turn: by attacking, or by a move `UpdatePos`
+    /// accepted. The AI gets its turn in the next tick.
+    pub fn end_player_turn(&mut self) {
+        self.player_acted = true;
+    }
+
+    /// Whether the AI systems may act during this tick.
+    pub fn ai_may_act(&self) -> bool {
+        !self.is_turn_based() || self.ai_turn
+    }
+}
+
+/// Hands the turn to the AI in the tick after the player acted, in turn-based mode.
+///
+/// Every AI entity's `ActionLock` is lifted and any move it is still gliding through is
+/// finished at the start of its turn, so the locks the AI systems set afterwards allow
+/// exactly one action per entity however quickly the player acts.
+pub struct TurnSys;
+
+impl<'a> System<'a> for TurnSys {
+    type SystemData = (
+        Entities<'a>,
+        Write<'a, Turns>,
+        ReadStorage<'a, AIMarker>,
+        WriteStorage<'a, ActionLock>,
+        WriteStorage<'a, Glide>,
+        WriteStorage<'a, Position>,
+        WriteStorage<'a, Velocity>,
+    );
+
+    fn run(&mut self, (entities, mut turns, ai_marker, mut action_locks, mut glides, mut positions, mut velocities): Self::SystemData) {
+        let player_acted = std::mem::take(&mut turns.player_acted);
+        turns.ai_turn = turns.is_turn_based() && player_acted;
+
+        if turns.ai_turn {
+            let locked = (&entities, &ai_marker, &action_locks).join()
+                .map(|(entity, _, _)| entity)
+                .collect::<Vec<_>>();
+            for entity in locked {
+                action_locks.remove(entity);
+            }
+
+            let gliding = (&entities, &ai_marker, &glides).join()
+                .map(|(entity, _, glide)| (entity, glide.to))
+                .collect::<Vec<_>>();
+            for (entity, (x, y)) in gliding {
+                glides.remove(entity);
+                if let Some(pos) = positions.get_mut(entity) {
+                    pos.x = x;
+                    pos.y = y;
+                }
+                if let Some(velocity) = velocities.get_mut(entity) {
+                    velocity.x = 0.0;
+                    velocity.y = 0.0;
+                }
+            }
+        }
+    }
+}
@@ -1,7 +1,10 @@
-use std::cmp::Ordering;
 use std::sync::{Arc, Mutex};
-use specs::{Entities, Read, ReadStorage, System, WriteStorage};
-use crate::{GridCoords, GridDimensions, NewGridCoords, Position};
+use specs::{Entities, Read, ReadStorage, System, Write, WriteStorage};
+use crate::collisions_sys::{MoveRequest, PositionsMap};
+use crate::game_clock::GameClock;
+use crate::movement_sys::MovementSettings;
+use crate::turn_sys::Turns;
+use crate::{Death, Glide, GridCoords, GridDimensions, NewGridCoords, PlayerMarker, Position};
 
 pub struct UpdatePos;
 
@@ -10,50 +13,63 @@ impl<'a> System<'a> for UpdatePos {
                         Read<'a, Arc<Mutex<GridDimensions>>>,
                         WriteStorage<'a, NewGridCoords>,
                        WriteStorage<'a, GridCoords>,
-                       WriteStorage<'a, Position>);
+                       WriteStorage<'a, Position>,
+                       ReadStorage<'a, PlayerMarker>,
+                       ReadStorage<'a, Death>,
+                       Write<'a, PositionsMap>,
+                       WriteStorage<'a, Glide>,
+                       Read<'a, GameClock>,
+                       Read<'a, MovementSettings>,
+                       Write<'a, Turns>);
 
-    fn run(&mut self, (entities, grid_dims, mut new_coords, mut coords, mut pos): Self::SystemData) {
+    fn run(&mut self, (entities, grid_dims, mut new_coords, mut coords, mut pos, player, death, mut positions_map,
+                       mut glides, clock, settings, mut turns): Self::SystemData) {
         use specs::Join;
 
-        // let x_step = grid_dims.tile_dims.0;
-        // let y_step = grid_dims.tile_dims.1;
-
-        let mut updated_entities = Vec::new();
-
-        for (entity, new_ccord, coord, pos) in (&entities, &new_coords, &mut coords, &mut pos).join() {
-            // let mut x_diff = (coord.x.abs_diff(new_ccord.x) as f64);
-            // let mut y_diff = (coord.y.abs_diff(new_ccord.y) as f64);
-            //
-            // match coord.x.cmp(&new_ccord.x) {
-            //     Ordering::Less => {
-            //         // sprite
-            //     }
-            //     Ordering::Equal => {}
-            //     Ordering::Greater => {
-            //         x_diff *= -1.0;
-            //     }
-            // }
-            //
-            // match coord.y.cmp(&new_ccord.y) {
-            //     Ordering::Less => {}
-            //     Ordering::Equal => {}
-            //     Ordering::Greater => {
-            //         y_diff *= -1.0;
-            //     }
-            // }
+        positions_map.rebuild((&entities, &coords, !&death).join().map(|(e, c, _)| (e, (c.x, c.y))));
+
+        // The player's moves are resolved before anyone else's.
+        let requests = (&entities, &new_coords, &coords).join()
+            .map(|(entity, new_coord, coord)| MoveRequest {
+                entity,
+                priority: if player.contains(entity) { 0 } else { 1 },
+                from: (coord.x, coord.y),
+                to: (new_coord.x, new_coord.y),
+            })
+            .collect::<Vec<_>>();
+        let accepted = positions_map.resolve_moves(requests);
+
+        for request in accepted {
+            // A move the player asked for only uses up their turn once it is made.
+            if player.contains(request.entity) {
+                turns.end_player_turn();
+            }
+            let (Some(coord), Some(pos)) = (coords.get_mut(request.entity), pos.get_mut(request.entity)) else {
+                continue;
+            };
+            let (x, y) = request.to;
 
             let grid_dims = grid_dims.lock().unwrap();
 
-            pos.x  = grid_dims.find_position_for_gridx(new_ccord.x);
-            pos.y = grid_dims.find_position_for_gridx(new_ccord.y);
-            coord.x = new_ccord.x;
-            coord.y = new_ccord.y;
-            updated_entities.push(entity);
-        }
+            let target = grid_dims.tile_to_world((x, y));
+            coord.x = x;
+            coord.y = y;
 
-        for entity in updated_entities {
-            new_coords.remove(entity);
+            // The tile is taken right away; only the drawn position follows over time.
+            if settings.duration.is_zero() {
+                pos.x = target.0;
+                pos.y = target.1;
+            } else {
+                glides.insert(request.entity, Glide {
+                    from: (pos.x, pos.y),
+                    to: target,
+                    started: clock.now(),
+                }).expect("Glide component added");
+            }
         }
 
+        // Rejected requests are dropped too; the entity stays where it is.
+        new_coords.clear();
+
     }
 }